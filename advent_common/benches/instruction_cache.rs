@@ -0,0 +1,75 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use advent_common::intcode::parameters::UnaryParams;
+use advent_common::intcode::{Executor, OpCode, Program, Runner, VMType, VecPort, VM};
+
+static BOOST: &str = include_str!("../../day_9/input.txt");
+const ROUNDS: u32 = 20;
+
+fn boost(program: &Program, cached: bool) -> Result<(i64, Duration)> {
+    let start = Instant::now();
+    let mut answer = 0;
+    for _ in 0..ROUNDS {
+        let mut vm = VM::new();
+        vm.use_instruction_cache(cached);
+        vm.load_program(program)?;
+        let mut port = VecPort::new();
+        port.input(2);
+        for output in Executor::run(vm, port) {
+            answer = output?;
+        }
+    }
+    Ok((answer, start.elapsed() / ROUNDS))
+}
+
+/// Decodes and executes one instruction at a time, the way the vm did
+/// before it had a cache, fuel, history or observers, so the bench shows
+/// what those cost per step.
+fn decode_loop(program: &Program) -> Result<(i64, Duration)> {
+    let start = Instant::now();
+    let mut answer = 0;
+    for _ in 0..ROUNDS {
+        let mut vm = VM::new();
+        vm.load_program(program)?;
+        loop {
+            match vm.current_instruction()? {
+                OpCode::Exit => break,
+                OpCode::InputInteger(UnaryParams { value }) => {
+                    *value.read_mut(&mut vm)? = 2;
+                    vm.advance(2);
+                }
+                OpCode::OutputInteger(UnaryParams { value }) => {
+                    answer = value.read(&vm)?;
+                    vm.advance(2);
+                }
+                inst => {
+                    inst.exec(&mut vm)?;
+                }
+            }
+        }
+    }
+    Ok((answer, start.elapsed() / ROUNDS))
+}
+
+fn main() -> Result<()> {
+    let program = Program::from_source(BOOST)?;
+    let (decoded_answer, decoded) = decode_loop(&program)?;
+    let (uncached_answer, uncached) = boost(&program, false)?;
+    let (cached_answer, cached) = boost(&program, true)?;
+    assert_eq!(
+        (decoded_answer, uncached_answer),
+        (cached_answer, cached_answer),
+        "every way of running should give the same answer"
+    );
+    println!("boost part 2, {} rounds each", ROUNDS);
+    println!("decode loop\t{:?} per run", decoded);
+    println!("uncached\t{:?} per run", uncached);
+    println!("cached\t\t{:?} per run", cached);
+    println!(
+        "speedup\t\t{:.2}x over the decode loop",
+        decoded.as_secs_f64() / cached.as_secs_f64()
+    );
+    Ok(())
+}
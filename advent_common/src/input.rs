@@ -30,8 +30,17 @@ impl DayInput {
     where
         F: Fn(&mut dyn BufRead) -> Result<O>,
     {
-        if let Some(arg) = std::env::args().nth(1) {
-            let p = Path::new(&arg);
+        self.with_input_from(std::env::args().nth(1).as_deref(), f)
+    }
+
+    /// Like `with_input`, but takes the argument naming the input instead
+    /// of reading the first command line argument.
+    pub fn with_input_from<F, O>(&self, arg: Option<&str>, f: F) -> Result<O>
+    where
+        F: Fn(&mut dyn BufRead) -> Result<O>,
+    {
+        if let Some(arg) = arg {
+            let p = Path::new(arg);
             Ok(if p.exists() {
                 let file = File::open(arg)?;
                 f(&mut BufReader::new(file))?
@@ -73,7 +82,7 @@ pub fn read_from_stdin() -> BufReader<Stdin> {
     BufReader::new(std::io::stdin())
 }
 
-pub fn read_from_locked_stdin(stdin: &Stdin) -> BufReader<StdinLock> {
+pub fn read_from_locked_stdin(stdin: &Stdin) -> BufReader<StdinLock<'_>> {
     BufReader::new(stdin.lock())
 }
 
@@ -0,0 +1,154 @@
+//! Facts read off a program's code without running it. Code reached only
+//! through computed jumps isn't seen, and writes through relative mode
+//! parameters are ignored when deciding which cells are constants.
+
+use std::collections::BTreeSet;
+
+use crate::intcode::disassembler::{Disassembly, WordKind};
+use crate::intcode::opcodes::OpCode;
+use crate::intcode::parameters::{ConditionParams, Parameter};
+use crate::intcode::{Program, Word};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum JumpTarget<W = i64> {
+    /// The target is written into the instruction.
+    Immediate(W),
+    /// The target is read from memory when the jump executes.
+    Computed(Parameter<W>),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Analysis<W = i64> {
+    /// Addresses of every instruction the trace reached.
+    pub reachable: BTreeSet<usize>,
+    /// Addresses of `InputInteger` instructions.
+    pub inputs: Vec<usize>,
+    /// Addresses of `OutputInteger` instructions.
+    pub outputs: Vec<usize>,
+    /// Every conditional jump and where it goes.
+    pub jumps: Vec<(usize, JumpTarget<W>)>,
+    /// Instructions that write into code, with the address they write.
+    pub self_modifying: Vec<(usize, usize)>,
+    /// Cells that instructions read but nothing writes.
+    pub constants: BTreeSet<usize>,
+}
+
+impl<W: Word> Analysis<W> {
+    pub fn new(words: &[W]) -> Self {
+        let disassembly = Disassembly::new(words);
+        let mut analysis = Self {
+            reachable: BTreeSet::new(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            jumps: Vec::new(),
+            self_modifying: Vec::new(),
+            constants: BTreeSet::new(),
+        };
+        let mut read = BTreeSet::new();
+        let mut written = BTreeSet::new();
+        for (address, code) in disassembly.instructions() {
+            analysis.reachable.insert(address);
+            match code {
+                OpCode::InputInteger(_) => analysis.inputs.push(address),
+                OpCode::OutputInteger(_) => analysis.outputs.push(address),
+                OpCode::JumpIfTrue(ConditionParams { location, .. })
+                | OpCode::JumpIfFalse(ConditionParams { location, .. }) => {
+                    let target = match location {
+                        Parameter::Immediate(target) => JumpTarget::Immediate(target.clone()),
+                        _ => JumpTarget::Computed(location.clone()),
+                    };
+                    analysis.jumps.push((address, target));
+                }
+                _ => {}
+            }
+            for parameter in code.read_parameters() {
+                if let Parameter::Reference(cell) = parameter {
+                    read.insert(cell);
+                }
+            }
+            if let Some(Parameter::Reference(cell)) = code.write_parameter() {
+                written.insert(cell);
+                if disassembly.kind(cell) == Some(WordKind::Code) {
+                    analysis.self_modifying.push((address, cell));
+                }
+            }
+        }
+        analysis.constants = read.difference(&written).copied().collect();
+        analysis
+    }
+
+    /// Whether any jump's target depends on memory.
+    pub fn has_computed_jumps(&self) -> bool {
+        self.jumps
+            .iter()
+            .any(|(_, target)| matches!(target, JumpTarget::Computed(_)))
+    }
+}
+
+impl<W: Word> Program<W> {
+    pub fn analyze(&self) -> Analysis<W> {
+        Analysis::new(self.as_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn reports_io_jumps_and_constants() -> Result<()> {
+        let program = Program::from_assembly(
+            "
+                    in      &n
+            loop:   mul     &n, &factor, &n
+                    out     &n
+                    lt      &n, &limit, &flag
+                    jt      &flag, iloop
+                    jf      i0, &exit
+            end:    exit
+            n:      data    0
+            factor: data    3
+            limit:  data    100
+            flag:   data    0
+            exit:   data    end
+            ",
+        )?;
+        let analysis = program.analyze();
+        assert_eq!(
+            analysis.reachable.iter().copied().collect::<Vec<_>>(),
+            vec![0, 2, 6, 8, 12, 15]
+        );
+        assert_eq!(analysis.inputs, vec![0]);
+        assert_eq!(analysis.outputs, vec![6]);
+        assert_eq!(
+            analysis.jumps,
+            vec![
+                (12, JumpTarget::Immediate(2)),
+                (15, JumpTarget::Computed(Parameter::Reference(23))),
+            ]
+        );
+        assert!(analysis.has_computed_jumps());
+        assert_eq!(analysis.self_modifying, vec![]);
+        assert_eq!(
+            analysis.constants.iter().copied().collect::<Vec<_>>(),
+            vec![20, 21, 23]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn flags_writes_into_code() -> Result<()> {
+        let program = Program::from_assembly(
+            "
+            loop:   out     i0
+                    add     &loop+1, i1, &loop+1
+                    jt      i1, iloop
+            ",
+        )?;
+        let analysis = program.analyze();
+        assert_eq!(analysis.self_modifying, vec![(2, 1)]);
+        assert!(!analysis.constants.contains(&1));
+        Ok(())
+    }
+}
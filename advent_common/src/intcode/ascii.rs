@@ -0,0 +1,113 @@
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Stdin, Stdout, Write};
+
+use anyhow::Result;
+
+use crate::intcode::errors::{ErrorKinds, IOError};
+use crate::intcode::{Executor, Program, ReadInt, Runner, VMType, Word, WriteInt, VM};
+
+const NEWLINE: i64 = 10;
+
+pub fn ascii_stdport() -> AsciiPort<BufReader<Stdin>, Stdout> {
+    AsciiPort::new(BufReader::new(std::io::stdin()), std::io::stdout())
+}
+
+/// A port for programs that speak ASCII. Each line read from the input is
+/// sent one character at a time followed by a newline, and output in
+/// `0..=127` is written as characters. Anything larger, which such
+/// programs use for their final answer, is written as a number on its own
+/// line.
+pub struct AsciiPort<I: BufRead, O: Write> {
+    buffer: String,
+    pending: VecDeque<i64>,
+    input: I,
+    output: O,
+}
+
+impl<I: BufRead, O: Write> AsciiPort<I, O> {
+    pub fn new(input: I, output: O) -> Self {
+        Self {
+            buffer: String::new(),
+            pending: VecDeque::new(),
+            input,
+            output,
+        }
+    }
+}
+
+impl<I: BufRead, O: Write, W: Word> ReadInt<W> for AsciiPort<I, O> {
+    fn read_int(&mut self) -> Result<W> {
+        if self.pending.is_empty() {
+            self.output
+                .flush()
+                .map_err(|e| ErrorKinds::IOError(IOError::OutputError(e)))?;
+            self.buffer.clear();
+            let read = self
+                .input
+                .read_line(&mut self.buffer)
+                .map_err(|e| ErrorKinds::IOError(IOError::InputError(e)))?;
+            if read == 0 {
+                return Err(ErrorKinds::IOError(IOError::OutOfStaticInputError).into());
+            }
+            let line = self.buffer.trim_end_matches(&['\r', '\n'][..]);
+            self.pending.extend(line.chars().map(|c| c as i64));
+            self.pending.push_back(NEWLINE);
+        }
+        Ok(W::from_i64(self.pending.pop_front().unwrap_or(NEWLINE)))
+    }
+}
+
+impl<I: BufRead, O: Write, W: Word> WriteInt<W> for AsciiPort<I, O> {
+    fn write_int(&mut self, i: W) -> Result<()> {
+        match i.to_i64().filter(|c| (0..=127).contains(c)) {
+            Some(c) => write!(self.output, "{}", c as u8 as char),
+            None => writeln!(self.output, "{}", i),
+        }
+        .map_err(|e| ErrorKinds::IOError(IOError::OutputError(e)).into())
+    }
+}
+
+/// Runs `program` with `script` as its input, returning everything it
+/// printed.
+pub fn run_script<W: Word>(program: &Program<W>, script: &str) -> Result<String> {
+    let mut vm = VM::default();
+    vm.load_program(program)?;
+    let mut output = Vec::new();
+    for result in Executor::run(vm, AsciiPort::new(script.as_bytes(), &mut output)) {
+        result?;
+    }
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sends_lines_as_characters() -> Result<()> {
+        let mut port = AsciiPort::new("ab\r\nc".as_bytes(), Vec::new());
+        let read: Vec<i64> = (0..5).map(|_| port.read_int()).collect::<Result<_>>()?;
+        assert_eq!(read, vec![97, 98, 10, 99, 10]);
+        assert!(ReadInt::<i64>::read_int(&mut port).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn runs_a_script() -> Result<()> {
+        let program = Program::from_assembly(
+            "
+            loop:   in      &c
+                    out     &c
+                    eq      &c, i10, &done
+                    jf      &done, iloop
+                    out     i1000
+                    exit
+            c:      data    0
+            done:   data    0
+            ",
+        )?;
+        assert_eq!(run_script(&program, "hi")?, "hi\n1000\n");
+        assert!(run_script(&program, "").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,360 @@
+//! Assembles the mnemonics `Display for OpCode` prints. Parameters use the
+//! disassembler's `i`, `&` and `r` prefixes and can name a label offset by
+//! `+N` or `-N`; `data` emits raw words and `;` starts a comment.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+use crate::intcode::decoding;
+use crate::intcode::errors::{AssemblyError, ErrorKinds};
+use crate::intcode::{OpCode, Program};
+
+static FIELD_NAMES: [&str; 6] = ["left:", "right:", "out:", "val:", "test:", "jump_to:"];
+
+struct Mnemonic {
+    name: &'static str,
+    opcode: i64,
+    out: Option<usize>,
+}
+
+impl Mnemonic {
+    fn arity(&self) -> usize {
+        decoding::arity(self.opcode).unwrap_or_default()
+    }
+}
+
+static MNEMONICS: [Mnemonic; 10] = [
+    Mnemonic {
+        name: "add",
+        opcode: 1,
+        out: Some(2),
+    },
+    Mnemonic {
+        name: "mul",
+        opcode: 2,
+        out: Some(2),
+    },
+    Mnemonic {
+        name: "in",
+        opcode: 3,
+        out: Some(0),
+    },
+    Mnemonic {
+        name: "out",
+        opcode: 4,
+        out: None,
+    },
+    Mnemonic {
+        name: "jt",
+        opcode: 5,
+        out: None,
+    },
+    Mnemonic {
+        name: "jf",
+        opcode: 6,
+        out: None,
+    },
+    Mnemonic {
+        name: "lt",
+        opcode: 7,
+        out: Some(2),
+    },
+    Mnemonic {
+        name: "eq",
+        opcode: 8,
+        out: Some(2),
+    },
+    Mnemonic {
+        name: "srb",
+        opcode: 9,
+        out: None,
+    },
+    Mnemonic {
+        name: "exit",
+        opcode: 99,
+        out: None,
+    },
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Reference = 0,
+    Immediate = 1,
+    Relative = 2,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    Number(i64),
+    Label(String, i64),
+}
+
+impl Value {
+    fn parse(token: &str) -> Result<Self, AssemblyError> {
+        if let Ok(n) = token.parse() {
+            return Ok(Value::Number(n));
+        }
+        let (name, offset) = match token.find(['+', '-']) {
+            Some(idx) => {
+                let offset = token[idx..]
+                    .trim_start_matches('+')
+                    .parse()
+                    .map_err(|_| AssemblyError::InvalidParameter(token.to_owned()))?;
+                (&token[..idx], offset)
+            }
+            None => (token, 0),
+        };
+        if !is_label(name) {
+            return Err(AssemblyError::InvalidParameter(token.to_owned()));
+        }
+        Ok(Value::Label(name.to_owned(), offset))
+    }
+
+    fn resolve(&self, labels: &HashMap<String, usize>) -> Result<i64, AssemblyError> {
+        match self {
+            Value::Number(n) => Ok(*n),
+            Value::Label(name, offset) => labels
+                .get(name)
+                .map(|&address| address as i64 + offset)
+                .ok_or_else(|| AssemblyError::UndefinedLabel(name.clone())),
+        }
+    }
+}
+
+struct Operand {
+    mode: Mode,
+    value: Value,
+    source: String,
+}
+
+impl Operand {
+    fn parse(token: &str) -> Result<Self, AssemblyError> {
+        let mode = match token.chars().next() {
+            Some('i') => Mode::Immediate,
+            Some('&') => Mode::Reference,
+            Some('r') => Mode::Relative,
+            _ => return Err(AssemblyError::InvalidParameter(token.to_owned())),
+        };
+        Ok(Self {
+            mode,
+            value: Value::parse(&token[1..])?,
+            source: token.to_owned(),
+        })
+    }
+}
+
+enum Statement {
+    Instruction(&'static Mnemonic, Vec<Operand>),
+    Data(Vec<Value>),
+}
+
+impl Statement {
+    fn len(&self) -> usize {
+        match self {
+            Statement::Instruction(m, _) => m.arity() + 1,
+            Statement::Data(values) => values.len(),
+        }
+    }
+
+    fn parse(mnemonic: &str, operands: Vec<&str>) -> Result<Self, AssemblyError> {
+        if mnemonic == "data" {
+            return Ok(Statement::Data(
+                operands
+                    .into_iter()
+                    .map(Value::parse)
+                    .collect::<Result<_, _>>()?,
+            ));
+        }
+        let m = MNEMONICS
+            .iter()
+            .find(|m| m.name == mnemonic)
+            .ok_or_else(|| AssemblyError::UnknownMnemonic(mnemonic.to_owned()))?;
+        if operands.len() != m.arity() {
+            return Err(AssemblyError::WrongParameterCount {
+                mnemonic: mnemonic.to_owned(),
+                expected: m.arity(),
+                found: operands.len(),
+            });
+        }
+        let operands: Vec<Operand> = operands
+            .into_iter()
+            .map(Operand::parse)
+            .collect::<Result<_, _>>()?;
+        if let Some(out) = m.out.map(|idx| &operands[idx]) {
+            if out.mode == Mode::Immediate {
+                return Err(AssemblyError::ImmediateModeOutput(out.source.clone()));
+            }
+        }
+        Ok(Statement::Instruction(m, operands))
+    }
+
+    fn encode(
+        &self,
+        labels: &HashMap<String, usize>,
+        output: &mut Vec<i64>,
+    ) -> Result<(), AssemblyError> {
+        match self {
+            Statement::Instruction(m, operands) => {
+                let mut modes = [0; 3];
+                let mut words = vec![m.opcode];
+                for (idx, operand) in operands.iter().enumerate() {
+                    let value = operand.value.resolve(labels)?;
+                    if operand.mode == Mode::Reference && value < 0 {
+                        return Err(AssemblyError::ReferenceLessThanZero(operand.source.clone()));
+                    }
+                    modes[2 - idx] = operand.mode as u8;
+                    words.push(value);
+                }
+                let code = OpCode::with_modes(m.opcode, &modes, &words)
+                    .expect("mnemonics only name the decoder's opcodes");
+                output.extend(code.encode());
+            }
+            Statement::Data(values) => {
+                for value in values {
+                    output.push(value.resolve(labels)?);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_label(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+fn parse_line(
+    line: &str,
+    address: usize,
+    labels: &mut HashMap<String, usize>,
+) -> Result<Option<Statement>, AssemblyError> {
+    let line = line.split(';').next().unwrap_or("");
+    let mut rest = line.trim();
+    while let Some(idx) = rest.find(':') {
+        let name = &rest[..idx];
+        if name.contains(char::is_whitespace) || name.contains('[') {
+            break;
+        }
+        if !is_label(name) {
+            return Err(AssemblyError::InvalidLabel(name.to_owned()));
+        }
+        if labels.insert(name.to_owned(), address).is_some() {
+            return Err(AssemblyError::DuplicateLabel(name.to_owned()));
+        }
+        rest = rest[idx + 1..].trim_start();
+    }
+    let mut tokens = rest
+        .split(|c: char| c == ',' || c == '[' || c == ']' || c.is_whitespace())
+        .map(|token| token.trim_end_matches('.'))
+        .filter(|token| !token.is_empty() && !FIELD_NAMES.contains(token));
+    match tokens.next() {
+        Some(mnemonic) => Statement::parse(mnemonic, tokens.collect()).map(Some),
+        None => Ok(None),
+    }
+}
+
+pub fn assemble<T: AsRef<str>>(source: T) -> Result<Program> {
+    let mut labels = HashMap::new();
+    let mut statements = Vec::new();
+    let mut address = 0;
+    for (idx, line) in source.as_ref().lines().enumerate() {
+        let statement = parse_line(line, address, &mut labels)
+            .map_err(|e| ErrorKinds::AssemblyError(idx + 1, e))?;
+        if let Some(statement) = statement {
+            address += statement.len();
+            statements.push((idx + 1, statement));
+        }
+    }
+    let mut output = Vec::with_capacity(address);
+    for (line, statement) in statements {
+        statement
+            .encode(&labels, &mut output)
+            .map_err(|e| ErrorKinds::AssemblyError(line, e))?;
+    }
+    Ok(Program::from(output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::testing::{load, run};
+
+    #[test]
+    fn encodes_parameter_modes() -> Result<()> {
+        let program = assemble("add i1, &4, r-3\nmul r2, i-7, &0\nexit")?;
+        assert_eq!(
+            program,
+            Program::from_source("20101,1,4,-3,1202,2,-7,0,99")?,
+            "mode digits should be written in the places OpCode::parse reads them from"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn resolves_labels_and_data() -> Result<()> {
+        let program = assemble(
+            "
+            ; doubles every input until it sees a zero
+            loop:   in      &n
+                    jf      &n, iend
+                    mul     &n, i2, &n
+                    out     &n
+                    jt      i1, iloop
+            end:    exit
+            n:      data    0
+            ",
+        )?;
+        assert_eq!(
+            program,
+            Program::from_source("3,15,1006,15,14,1002,15,2,15,4,15,1105,1,0,99,0")?
+        );
+        assert_eq!(run(load(&program)?, &[1, 5, 21, 0])?, vec![2, 10, 42]);
+        Ok(())
+    }
+
+    #[test]
+    fn label_offsets() -> Result<()> {
+        let program = assemble("out &table+2\nexit\ntable: data 7, 8, 9, table")?;
+        assert_eq!(program, Program::from_source("4,5,99,7,8,9,3")?);
+        Ok(())
+    }
+
+    #[test]
+    fn round_trips_disassembled_opcodes() -> Result<()> {
+        let source = vec![
+            21101, 1, 2, -4, 1002, 5, -1, 6, 3, 9, 204, -11, 1105, 1, 7, 6, 0, 3, 109, 19, 1107, 1,
+            2, 3, 8, 1, 2, 3, 99,
+        ];
+        let mut listing = String::new();
+        let mut address = 0;
+        while address < source.len() {
+            let code = OpCode::parse(&source[address..])?;
+            listing.push_str(&format!("{}\n", code));
+            address += code.len();
+        }
+        assert_eq!(assemble(&listing)?, Program::from(source));
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        for source in &[
+            "nop",
+            "add i1, i2",
+            "add i1, i2, i3",
+            "out x",
+            "out &missing",
+            "a: exit\na: exit",
+            "out &-1",
+        ] {
+            assert!(assemble(source).is_err(), "{} should not assemble", source);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use crate::intcode::opcodes::OpCode;
+
+const MAX_INSTRUCTION_LENGTH: usize = 4;
+const MAX_CACHED_ADDRESS: usize = 1 << 16;
+
+#[derive(Clone, Debug)]
+pub(crate) struct InstructionCache<W = i64> {
+    enabled: bool,
+    entries: Vec<Option<OpCode<W>>>,
+}
+
+impl<W: Clone> InstructionCache<W> {
+    pub fn new() -> Self {
+        Self {
+            enabled: true,
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.clear();
+    }
+
+    pub fn get(&self, address: usize) -> Option<&OpCode<W>> {
+        self.entries.get(address).and_then(Option::as_ref)
+    }
+
+    pub fn insert(&mut self, address: usize, code: &OpCode<W>) {
+        if !self.enabled || address >= MAX_CACHED_ADDRESS {
+            return;
+        }
+        if address >= self.entries.len() {
+            self.entries.resize(address + 1, None);
+        }
+        self.entries[address] = Some(code.clone());
+    }
+
+    pub fn invalidate(&mut self, address: usize) {
+        let start = address.saturating_sub(MAX_INSTRUCTION_LENGTH - 1);
+        let end = (address + 1).min(self.entries.len());
+        for entry in self.entries.iter_mut().take(end).skip(start) {
+            *entry = None;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intcode::testing::load;
+    use crate::intcode::{Executor, Program, Runner, VecPort};
+    use anyhow::Result;
+
+    fn run(program: &Program, cached: bool) -> Result<Vec<i64>> {
+        let mut vm = load(program)?;
+        vm.use_instruction_cache(cached);
+        Executor::run(vm, VecPort::new()).take(10).collect()
+    }
+
+    #[test]
+    fn sees_patched_parameters() -> Result<()> {
+        let program = Program::from_assembly(
+            "
+            loop:   out     i0
+                    add     &loop+1, i1, &loop+1
+                    lt      &loop+1, i3, &flag
+                    jt      &flag, iloop
+                    exit
+            flag:   data    0
+            ",
+        )?;
+        assert_eq!(run(&program, true)?, vec![0, 1, 2]);
+        assert_eq!(run(&program, false)?, vec![0, 1, 2]);
+        Ok(())
+    }
+
+    #[test]
+    fn sees_patched_opcodes() -> Result<()> {
+        let program = Program::from_assembly(
+            "
+            loop:   out     i5
+                    add     i99, i0, &loop
+                    jt      i1, iloop
+            ",
+        )?;
+        assert_eq!(run(&program, true)?, vec![5]);
+        Ok(())
+    }
+}
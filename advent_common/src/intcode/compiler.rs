@@ -0,0 +1,887 @@
+//! Compiles a small structured language (functions, `let`, `if`, `while`,
+//! integer expressions, `input()` and `output()`) to assembly.
+//!
+//! Frames live on a stack past the end of the program and are addressed
+//! through the relative base. Slot 0 holds the return address, arguments
+//! start at slot 1 and the result comes back in slot 1.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+
+use anyhow::Result;
+
+use crate::intcode::assembler;
+use crate::intcode::errors::{CompileError, ErrorKinds};
+use crate::intcode::Program;
+
+static KEYWORDS: [&str; 8] = [
+    "fn", "let", "if", "else", "while", "return", "input", "output",
+];
+static SYMBOLS: [&str; 17] = [
+    "==", "!=", "<=", ">=", "(", ")", "{", "}", ",", ";", "=", "+", "-", "*", "<", ">", "!",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Symbol(&'static str),
+    End,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Name(name) => write!(f, "{}", name),
+            Token::Symbol(symbol) => write!(f, "{}", symbol),
+            Token::End => write!(f, "the end of the program"),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>> {
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    for (idx, text) in source.lines().enumerate() {
+        line = idx + 1;
+        let error = |e| ErrorKinds::CompileError(line, e);
+        let mut rest = match text.find("//") {
+            Some(comment) => &text[..comment],
+            None => text,
+        };
+        loop {
+            rest = rest.trim_start();
+            let c = match rest.chars().next() {
+                Some(c) => c,
+                None => break,
+            };
+            let (token, len) = if c.is_ascii_alphanumeric() || c == '_' {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len());
+                let word = &rest[..len];
+                if c.is_ascii_digit() {
+                    let n = word
+                        .parse()
+                        .map_err(|_| error(CompileError::InvalidNumber(word.to_owned())))?;
+                    (Token::Number(n), len)
+                } else {
+                    (Token::Name(word.to_owned()), len)
+                }
+            } else {
+                let symbol = SYMBOLS
+                    .iter()
+                    .find(|symbol| rest.starts_with(*symbol))
+                    .ok_or_else(|| error(CompileError::UnexpectedCharacter(c)))?;
+                (Token::Symbol(symbol), symbol.len())
+            };
+            tokens.push((line, token));
+            rest = &rest[len..];
+        }
+    }
+    tokens.push((line, Token::End));
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+/// Binary operators from the loosest binding to the tightest.
+static PRECEDENCE: [&[(&str, BinaryOp)]; 4] = [
+    &[("==", BinaryOp::Equal), ("!=", BinaryOp::NotEqual)],
+    &[
+        ("<", BinaryOp::Less),
+        ("<=", BinaryOp::LessOrEqual),
+        (">", BinaryOp::Greater),
+        (">=", BinaryOp::GreaterOrEqual),
+    ],
+    &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+    &[("*", BinaryOp::Mul)],
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UnaryOp {
+    Negate,
+    Not,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Number(i64),
+    Variable(String),
+    Input,
+    Call(String, Vec<Expr>),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum StmtKind {
+    Let(String, Expr),
+    Assign(String, Expr),
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    While(Expr, Vec<Stmt>),
+    Return(Option<Expr>),
+    Output(Expr),
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Stmt {
+    line: usize,
+    kind: StmtKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Function {
+    line: usize,
+    name: String,
+    params: Vec<String>,
+    body: Vec<Stmt>,
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].1
+    }
+
+    fn line(&self) -> usize {
+        self.tokens[self.pos].0
+    }
+
+    fn is(&self, text: &str) -> bool {
+        match self.peek() {
+            Token::Symbol(symbol) => *symbol == text,
+            Token::Name(name) => name == text,
+            _ => false,
+        }
+    }
+
+    fn eat(&mut self, text: &str) -> bool {
+        let found = self.is(text);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn unexpected(&self, expected: &str) -> anyhow::Error {
+        let e = match self.peek() {
+            Token::End => CompileError::UnexpectedEnd(expected.to_owned()),
+            found => CompileError::UnexpectedToken {
+                expected: expected.to_owned(),
+                found: found.to_string(),
+            },
+        };
+        ErrorKinds::CompileError(self.line(), e).into()
+    }
+
+    fn expect(&mut self, text: &str) -> Result<()> {
+        if self.eat(text) {
+            Ok(())
+        } else {
+            Err(self.unexpected(text))
+        }
+    }
+
+    fn is_assignment(&self) -> bool {
+        matches!(self.tokens.get(self.pos + 1), Some((_, Token::Symbol("="))))
+    }
+
+    fn name(&mut self) -> Result<String> {
+        match self.peek() {
+            Token::Name(name) if !KEYWORDS.contains(&name.as_str()) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => Err(self.unexpected("a name")),
+        }
+    }
+
+    /// Parses a comma separated list up to the closing parenthesis.
+    fn list<T>(&mut self, mut item: impl FnMut(&mut Self) -> Result<T>) -> Result<Vec<T>> {
+        let mut items = Vec::new();
+        if self.eat(")") {
+            return Ok(items);
+        }
+        loop {
+            items.push(item(self)?);
+            if self.eat(")") {
+                return Ok(items);
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn program(&mut self) -> Result<(Vec<Function>, Vec<Stmt>)> {
+        let mut functions = Vec::new();
+        let mut main = Vec::new();
+        while *self.peek() != Token::End {
+            if self.is("fn") {
+                functions.push(self.function()?);
+            } else {
+                main.push(self.statement()?);
+            }
+        }
+        Ok((functions, main))
+    }
+
+    fn function(&mut self) -> Result<Function> {
+        let line = self.line();
+        self.expect("fn")?;
+        let name = self.name()?;
+        self.expect("(")?;
+        let params = self.list(Self::name)?;
+        Ok(Function {
+            line,
+            name,
+            params,
+            body: self.block()?,
+        })
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>> {
+        self.expect("{")?;
+        let mut body = Vec::new();
+        while !self.eat("}") {
+            body.push(self.statement()?);
+        }
+        Ok(body)
+    }
+
+    fn statement(&mut self) -> Result<Stmt> {
+        let line = self.line();
+        let kind = if self.eat("let") {
+            let name = self.name()?;
+            self.expect("=")?;
+            StmtKind::Let(name, self.expr()?)
+        } else if self.eat("if") {
+            let condition = self.expr()?;
+            let then = self.block()?;
+            let otherwise = if !self.eat("else") {
+                Vec::new()
+            } else if self.is("if") {
+                vec![self.statement()?]
+            } else {
+                self.block()?
+            };
+            return Ok(Stmt {
+                line,
+                kind: StmtKind::If(condition, then, otherwise),
+            });
+        } else if self.eat("while") {
+            let condition = self.expr()?;
+            return Ok(Stmt {
+                line,
+                kind: StmtKind::While(condition, self.block()?),
+            });
+        } else if self.eat("return") {
+            StmtKind::Return(if self.is(";") {
+                None
+            } else {
+                Some(self.expr()?)
+            })
+        } else if self.eat("output") {
+            self.expect("(")?;
+            let value = self.expr()?;
+            self.expect(")")?;
+            StmtKind::Output(value)
+        } else if self.is_assignment() {
+            let name = self.name()?;
+            self.expect("=")?;
+            StmtKind::Assign(name, self.expr()?)
+        } else {
+            StmtKind::Expr(self.expr()?)
+        };
+        self.expect(";")?;
+        Ok(Stmt { line, kind })
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        self.binary(0)
+    }
+
+    fn binary_op(&self, level: usize) -> Option<BinaryOp> {
+        PRECEDENCE[level]
+            .iter()
+            .find(|(symbol, _)| self.is(symbol))
+            .map(|&(_, op)| op)
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr> {
+        if level == PRECEDENCE.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        while let Some(op) = self.binary_op(level) {
+            self.pos += 1;
+            let right = self.binary(level + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.eat("-") {
+            return Ok(match self.unary()? {
+                Expr::Number(n) => Expr::Number(-n),
+                operand => Expr::Unary(UnaryOp::Negate, Box::new(operand)),
+            });
+        }
+        if self.eat("!") {
+            return Ok(Expr::Unary(UnaryOp::Not, Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        match self.peek() {
+            Token::Number(n) => {
+                let n = *n;
+                self.pos += 1;
+                Ok(Expr::Number(n))
+            }
+            Token::Symbol("(") => {
+                self.pos += 1;
+                let expr = self.expr()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Token::Name(name) if name == "input" => {
+                self.pos += 1;
+                self.expect("(")?;
+                self.expect(")")?;
+                Ok(Expr::Input)
+            }
+            Token::Name(_) => {
+                let name = self.name()?;
+                if self.eat("(") {
+                    Ok(Expr::Call(name, self.list(Self::expr)?))
+                } else {
+                    Ok(Expr::Variable(name))
+                }
+            }
+            _ => Err(self.unexpected("an expression")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand {
+    Immediate(i64),
+    Slot(usize),
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Operand::Immediate(n) => write!(f, "i{}", n),
+            Operand::Slot(slot) => write!(f, "r{}", slot),
+        }
+    }
+}
+
+struct Generator<'a> {
+    arities: &'a HashMap<String, usize>,
+    lines: Vec<String>,
+    labels: usize,
+    line: usize,
+    in_function: bool,
+    scope: HashMap<String, usize>,
+    /// The first slot of the current frame that's free.
+    depth: usize,
+}
+
+impl<'a> Generator<'a> {
+    fn new(arities: &'a HashMap<String, usize>) -> Self {
+        Self {
+            arities,
+            lines: Vec::new(),
+            labels: 0,
+            line: 0,
+            in_function: false,
+            scope: HashMap::new(),
+            depth: 1,
+        }
+    }
+
+    fn error(&self, e: CompileError) -> anyhow::Error {
+        ErrorKinds::CompileError(self.line, e).into()
+    }
+
+    fn emit<T: AsRef<str>>(&mut self, instruction: T) {
+        self.lines.push(format!("        {}", instruction.as_ref()));
+    }
+
+    fn label(&mut self) -> String {
+        self.labels += 1;
+        format!("_{}", self.labels)
+    }
+
+    fn place(&mut self, label: &str) {
+        self.lines.push(format!("{}:", label));
+    }
+
+    fn alloc(&mut self) -> usize {
+        self.depth += 1;
+        self.depth - 1
+    }
+
+    fn variable(&self, name: &str) -> Result<usize> {
+        self.scope
+            .get(name)
+            .copied()
+            .ok_or_else(|| self.error(CompileError::UndefinedVariable(name.to_owned())))
+    }
+
+    fn main(&mut self, body: &[Stmt]) -> Result<()> {
+        self.emit("srb i_stack");
+        self.block(body)?;
+        self.emit("exit");
+        Ok(())
+    }
+
+    fn function(&mut self, function: &Function) -> Result<()> {
+        self.line = function.line;
+        self.in_function = true;
+        self.scope.clear();
+        for (idx, param) in function.params.iter().enumerate() {
+            if self.scope.insert(param.clone(), idx + 1).is_some() {
+                return Err(self.error(CompileError::DuplicateParameter(param.clone())));
+            }
+        }
+        self.depth = function.params.len() + 1;
+        self.place(&format!("fn_{}", function.name));
+        self.block(&function.body)?;
+        self.emit("add i0, i0, r1");
+        self.emit("jt i1, r0");
+        Ok(())
+    }
+
+    fn block(&mut self, body: &[Stmt]) -> Result<()> {
+        let (scope, depth) = (self.scope.clone(), self.depth);
+        for stmt in body {
+            self.statement(stmt)?;
+        }
+        self.scope = scope;
+        self.depth = depth;
+        Ok(())
+    }
+
+    fn statement(&mut self, stmt: &Stmt) -> Result<()> {
+        self.line = stmt.line;
+        let depth = self.depth;
+        match &stmt.kind {
+            StmtKind::Let(name, value) => {
+                let slot = self.alloc();
+                self.expr_into(value, slot)?;
+                self.scope.insert(name.clone(), slot);
+                self.depth = slot + 1;
+                return Ok(());
+            }
+            StmtKind::Assign(name, value) => {
+                let slot = self.variable(name)?;
+                self.expr_into(value, slot)?;
+            }
+            StmtKind::If(condition, then, otherwise) => {
+                let (other, end) = (self.label(), self.label());
+                let test = self.operand(condition)?;
+                self.emit(format!("jf {}, i{}", test, other));
+                self.block(then)?;
+                if !otherwise.is_empty() {
+                    self.emit(format!("jt i1, i{}", end));
+                }
+                self.place(&other);
+                self.block(otherwise)?;
+                self.place(&end);
+            }
+            StmtKind::While(condition, body) => {
+                let (top, end) = (self.label(), self.label());
+                self.place(&top);
+                let test = self.operand(condition)?;
+                self.emit(format!("jf {}, i{}", test, end));
+                self.block(body)?;
+                self.emit(format!("jt i1, i{}", top));
+                self.place(&end);
+            }
+            StmtKind::Return(value) => {
+                let value = match value {
+                    Some(value) => self.operand(value)?,
+                    None => Operand::Immediate(0),
+                };
+                if self.in_function {
+                    self.emit(format!("add {}, i0, r1", value));
+                    self.emit("jt i1, r0");
+                } else {
+                    self.emit("exit");
+                }
+            }
+            StmtKind::Output(value) => {
+                let value = self.operand(value)?;
+                self.emit(format!("out {}", value));
+            }
+            StmtKind::Expr(value) => {
+                self.operand(value)?;
+            }
+        }
+        self.depth = depth;
+        Ok(())
+    }
+
+    /// Where `expr`'s value can be read from, computing it into a fresh slot
+    /// unless it's a constant or a variable.
+    fn operand(&mut self, expr: &Expr) -> Result<Operand> {
+        Ok(match expr {
+            Expr::Number(n) => Operand::Immediate(*n),
+            Expr::Variable(name) => Operand::Slot(self.variable(name)?),
+            _ => {
+                let slot = self.alloc();
+                self.expr_into(expr, slot)?;
+                Operand::Slot(slot)
+            }
+        })
+    }
+
+    fn expr_into(&mut self, expr: &Expr, slot: usize) -> Result<()> {
+        let out = Operand::Slot(slot);
+        match expr {
+            Expr::Number(_) | Expr::Variable(_) => {
+                let value = self.operand(expr)?;
+                self.emit(format!("add {}, i0, {}", value, out));
+            }
+            Expr::Input => self.emit(format!("in {}", out)),
+            Expr::Call(name, args) => self.call(name, args, slot)?,
+            Expr::Unary(op, operand) => {
+                let value = self.operand(operand)?;
+                match op {
+                    UnaryOp::Negate => self.emit(format!("mul {}, i-1, {}", value, out)),
+                    UnaryOp::Not => self.emit(format!("eq {}, i0, {}", value, out)),
+                }
+            }
+            Expr::Binary(op, left, right) => {
+                let (left, right) = (self.operand(left)?, self.operand(right)?);
+                let (mnemonic, left, right, negate) = match op {
+                    BinaryOp::Add => ("add", left, right, false),
+                    BinaryOp::Sub => ("add", left, self.negated(right), false),
+                    BinaryOp::Mul => ("mul", left, right, false),
+                    BinaryOp::Equal => ("eq", left, right, false),
+                    BinaryOp::NotEqual => ("eq", left, right, true),
+                    BinaryOp::Less => ("lt", left, right, false),
+                    BinaryOp::GreaterOrEqual => ("lt", left, right, true),
+                    BinaryOp::Greater => ("lt", right, left, false),
+                    BinaryOp::LessOrEqual => ("lt", right, left, true),
+                };
+                self.emit(format!("{} {}, {}, {}", mnemonic, left, right, out));
+                if negate {
+                    self.emit(format!("eq {}, i0, {}", out, out));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn negated(&mut self, operand: Operand) -> Operand {
+        if let Operand::Immediate(n) = operand {
+            if let Some(negated) = n.checked_neg() {
+                return Operand::Immediate(negated);
+            }
+        }
+        let slot = self.alloc();
+        self.emit(format!("mul {}, i-1, r{}", operand, slot));
+        Operand::Slot(slot)
+    }
+
+    fn call(&mut self, name: &str, args: &[Expr], slot: usize) -> Result<()> {
+        match self.arities.get(name) {
+            None => return Err(self.error(CompileError::UndefinedFunction(name.to_owned()))),
+            Some(&expected) if expected != args.len() => {
+                return Err(self.error(CompileError::WrongArgumentCount {
+                    name: name.to_owned(),
+                    expected,
+                    found: args.len(),
+                }))
+            }
+            Some(_) => {}
+        }
+        let frame = self.depth;
+        self.depth += args.len() + 1;
+        for (idx, arg) in args.iter().enumerate() {
+            self.expr_into(arg, frame + idx + 1)?;
+        }
+        let back = self.label();
+        self.emit(format!("add i{}, i0, r{}", back, frame));
+        self.emit(format!("srb i{}", frame));
+        self.emit(format!("jt i1, ifn_{}", name));
+        self.place(&back);
+        self.emit(format!("srb i-{}", frame));
+        self.emit(format!("add r{}, i0, r{}", frame + 1, slot));
+        Ok(())
+    }
+}
+
+/// Compiles `source` to the assembly `assembler::assemble` accepts.
+pub fn compile_to_assembly<T: AsRef<str>>(source: T) -> Result<String> {
+    let mut parser = Parser {
+        tokens: tokenize(source.as_ref())?,
+        pos: 0,
+    };
+    let (functions, main) = parser.program()?;
+    let mut arities = HashMap::new();
+    for function in functions.iter() {
+        if arities
+            .insert(function.name.clone(), function.params.len())
+            .is_some()
+        {
+            return Err(ErrorKinds::CompileError(
+                function.line,
+                CompileError::DuplicateFunction(function.name.clone()),
+            )
+            .into());
+        }
+    }
+    let mut generator = Generator::new(&arities);
+    generator.main(&main)?;
+    for function in functions.iter() {
+        generator.function(function)?;
+    }
+    generator.place("_stack");
+    generator.emit("data 0");
+    Ok(generator.lines.join("\n"))
+}
+
+pub fn compile<T: AsRef<str>>(source: T) -> Result<Program> {
+    assembler::assemble(compile_to_assembly(source)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::testing;
+
+    fn run(source: &str, inputs: &[i64]) -> Result<Vec<i64>> {
+        testing::run(testing::load(&Program::compile(source)?)?, inputs)
+    }
+
+    fn error(source: &str) -> String {
+        match compile(source) {
+            Ok(_) => panic!("{} should not compile", source),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn evaluates_arithmetic() -> Result<()> {
+        let source = "
+            output(1 + 2 * 3);
+            output((1 + 2) * 3);
+            output(7 - 3 - 2);
+            output(-(4 - 6) * 2);
+            let x = input();
+            let y = input();
+            output(x - y);
+            output(y - x * -1);
+            output(-x);
+        ";
+        assert_eq!(run(source, &[10, 4])?, vec![7, 9, 2, 4, 6, 14, -10]);
+        Ok(())
+    }
+
+    #[test]
+    fn evaluates_comparisons() -> Result<()> {
+        let source = "
+            let a = input();
+            let b = input();
+            output(a == b);
+            output(a != b);
+            output(a < b);
+            output(a <= b);
+            output(a > b);
+            output(a >= b);
+            output(!a);
+        ";
+        assert_eq!(run(source, &[1, 2])?, vec![0, 1, 1, 1, 0, 0, 0]);
+        assert_eq!(run(source, &[2, 2])?, vec![1, 0, 0, 1, 0, 1, 0]);
+        assert_eq!(run(source, &[0, -1])?, vec![0, 1, 0, 0, 1, 1, 1]);
+        Ok(())
+    }
+
+    #[test]
+    fn runs_loops_and_branches() -> Result<()> {
+        let source = "
+            // Sums its input up to the first zero, then classifies the sum.
+            let sum = 0;
+            let n = input();
+            while n {
+                sum = sum + n;
+                n = input();
+            }
+            output(sum);
+            if sum < 0 {
+                output(-1);
+            } else if sum == 0 {
+                output(0);
+            } else {
+                output(1);
+            }
+        ";
+        assert_eq!(run(source, &[3, 4, 5, 0])?, vec![12, 1]);
+        assert_eq!(run(source, &[3, -3, 0])?, vec![0, 0]);
+        assert_eq!(run(source, &[-8, 0])?, vec![-8, -1]);
+        Ok(())
+    }
+
+    #[test]
+    fn scopes_variables_to_blocks() -> Result<()> {
+        let source = "
+            let x = 1;
+            if x {
+                let x = x + 1;
+                output(x);
+                x = 5;
+            }
+            output(x);
+            let y = 2;
+            output(y);
+        ";
+        assert_eq!(run(source, &[])?, vec![2, 1, 2]);
+        Ok(())
+    }
+
+    #[test]
+    fn calls_recursive_functions() -> Result<()> {
+        let source = "
+            let n = input();
+            output(fact(n));
+            output(fib(n));
+            output(is_even(n));
+            output(add3(1, add3(2, 3, fact(3)), 5));
+            greet();
+            return;
+            output(99);
+
+            fn fact(n) {
+                if n < 2 {
+                    return 1;
+                }
+                return n * fact(n - 1);
+            }
+
+            fn fib(n) {
+                let a = 0;
+                let b = 1;
+                while n > 0 {
+                    let next = a + b;
+                    a = b;
+                    b = next;
+                    n = n - 1;
+                }
+                return a;
+            }
+
+            fn is_even(n) {
+                if n == 0 {
+                    return 1;
+                }
+                return is_odd(n - 1);
+            }
+
+            fn is_odd(n) {
+                if n == 0 {
+                    return 0;
+                }
+                return is_even(n - 1);
+            }
+
+            fn add3(a, b, c) {
+                return a + b + c;
+            }
+
+            fn greet() {
+                output(72);
+                output(105);
+            }
+        ";
+        assert_eq!(run(source, &[10])?, vec![3628800, 55, 1, 17, 72, 105]);
+        assert_eq!(run(source, &[7])?, vec![5040, 13, 0, 17, 72, 105]);
+        Ok(())
+    }
+
+    #[test]
+    fn emits_assembly() -> Result<()> {
+        let assembly = compile_to_assembly("output(input() * 2);")?;
+        assert_eq!(
+            assembly.lines().map(|line| line.trim()).collect::<Vec<_>>(),
+            vec![
+                "srb i_stack",
+                "in r2",
+                "mul r2, i2, r1",
+                "out r1",
+                "exit",
+                "_stack:",
+                "data 0"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reports_errors_with_lines() {
+        assert_eq!(
+            error("let x = 1;\noutput(y);"),
+            "compile error on line 2: undefined variable y"
+        );
+        assert_eq!(
+            error("fn f(a) { return a; }\n\noutput(f(1, 2));"),
+            "compile error on line 3: f expects 1 arguments, found 2"
+        );
+        assert_eq!(
+            error("output(g());"),
+            "compile error on line 1: undefined function g"
+        );
+        assert_eq!(
+            error("fn f() {}\nfn f() {}"),
+            "compile error on line 2: function f is defined more than once"
+        );
+        assert_eq!(
+            error("fn f(a, a) {}"),
+            "compile error on line 1: parameter a is declared more than once"
+        );
+        assert_eq!(
+            error("let x = 1\noutput(x);"),
+            "compile error on line 2: expected ;, found output"
+        );
+        assert_eq!(
+            error("while 1 {\n"),
+            "compile error on line 1: expected an expression, found the end of the program"
+        );
+        assert_eq!(
+            error("let let = 1;"),
+            "compile error on line 1: expected a name, found let"
+        );
+        assert_eq!(
+            error("output(1 $ 2);"),
+            "compile error on line 1: unexpected character $"
+        );
+        assert_eq!(
+            error("{ let x = 1; }\nx = 2;"),
+            "compile error on line 1: expected an expression, found {"
+        );
+    }
+}
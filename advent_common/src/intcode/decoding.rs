@@ -0,0 +1,153 @@
+use anyhow::Result;
+
+use crate::intcode::errors::{DecodeError, ErrorKinds};
+
+pub(crate) static PLACES: [i64; 3] = [10000, 1000, 100];
+
+/// How instruction words are split into an opcode and parameter modes.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Decoding {
+    /// Mode digits must be 0, 1 or 2, digits past the last parameter must
+    /// be zero and negative instructions are rejected.
+    #[default]
+    Strict,
+    /// Accepts any mode digit, reading those above 1 as relative mode, and
+    /// ignores digits past the last parameter.
+    Lenient,
+}
+
+pub(crate) fn arity(opcode: i64) -> Option<usize> {
+    match opcode {
+        1 | 2 | 7 | 8 => Some(3),
+        5 | 6 => Some(2),
+        3 | 4 | 9 => Some(1),
+        99 => Some(0),
+        _ => None,
+    }
+}
+
+impl Decoding {
+    /// Splits `instruction` into its opcode and the modes of its third,
+    /// second and first parameters.
+    pub fn split(self, instruction: i64) -> Result<(i64, [u8; 3])> {
+        self.split_with(instruction, arity)
+    }
+
+    /// Like `split`, with `arity` giving how many parameters an opcode takes.
+    pub(crate) fn split_with<F>(self, instruction: i64, arity: F) -> Result<(i64, [u8; 3])>
+    where
+        F: Fn(i64) -> Option<usize>,
+    {
+        match self {
+            Decoding::Strict => strict(instruction, arity),
+            Decoding::Lenient => Ok(lenient(instruction)),
+        }
+    }
+}
+
+fn strict<F: Fn(i64) -> Option<usize>>(instruction: i64, arity: F) -> Result<(i64, [u8; 3])> {
+    if instruction < 0 {
+        return Err(ErrorKinds::DecodeError(DecodeError::NegativeInstruction(instruction)).into());
+    }
+    let opcode = instruction % 100;
+    let arity = arity(opcode).ok_or_else(|| ErrorKinds::UnknownOpcodeError(opcode.to_string()))?;
+    let mut modes = instruction / 100;
+    let mut parameters = [0; 3];
+    for parameter in 0..arity {
+        let mode = modes % 10;
+        if mode > 2 {
+            return Err(ErrorKinds::DecodeError(DecodeError::InvalidMode {
+                instruction,
+                parameter: parameter + 1,
+                mode,
+            })
+            .into());
+        }
+        parameters[2 - parameter] = mode as u8;
+        modes /= 10;
+    }
+    if modes != 0 {
+        return Err(ErrorKinds::DecodeError(DecodeError::UnusedModes {
+            instruction,
+            parameters: arity,
+        })
+        .into());
+    }
+    Ok((opcode, parameters))
+}
+
+fn lenient(mut instruction: i64) -> (i64, [u8; 3]) {
+    let mut parameters = [0; 3];
+    if instruction >= 0 {
+        for (idx, &place) in PLACES.iter().enumerate() {
+            parameters[idx] = (instruction / place).min(u8::MAX as i64) as u8;
+            instruction %= place;
+        }
+    }
+    (instruction, parameters)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{OpCode, Program, Runable, Status, VMType, VM};
+
+    fn error(decoding: Decoding, instruction: i64) -> String {
+        decoding
+            .split(instruction)
+            .expect_err("the instruction should be rejected")
+            .to_string()
+    }
+
+    #[test]
+    fn strict_decoding_rejects_invalid_modes() {
+        assert_eq!(Decoding::Strict.split(21101).ok(), Some((1, [2, 1, 1])));
+        assert_eq!(Decoding::Strict.split(99).ok(), Some((99, [0, 0, 0])));
+        assert_eq!(
+            error(Decoding::Strict, 30001),
+            "decode error: mode 3 of parameter 3 in 30001 isn't 0, 1 or 2"
+        );
+        assert_eq!(
+            error(Decoding::Strict, 1104),
+            "decode error: 1104 has mode digits past its 1 parameters"
+        );
+        assert_eq!(
+            error(Decoding::Strict, 199),
+            "decode error: 199 has mode digits past its 0 parameters"
+        );
+        assert_eq!(
+            error(Decoding::Strict, -1),
+            "decode error: instruction -1 is negative"
+        );
+        assert_eq!(
+            error(Decoding::Strict, 42),
+            "parse error: unknown opcode 42"
+        );
+    }
+
+    #[test]
+    fn lenient_decoding_reads_large_modes_as_relative() -> Result<()> {
+        assert_eq!(Decoding::Lenient.split(30001).ok(), Some((1, [3, 0, 0])));
+        assert_eq!(Decoding::Lenient.split(1104).ok(), Some((4, [0, 1, 1])));
+        let code = OpCode::decode(&[30001i64, 1, 2, 3], Decoding::Lenient)?;
+        assert_eq!(
+            code.to_string(),
+            "add\t\t[ left:\t&0001, right:\t&0002, out:\tr0003 ]."
+        );
+        assert!(OpCode::parse(&[30001i64, 1, 2, 3]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn the_vm_decodes_with_its_policy() -> Result<()> {
+        let program = Program::from_source("109,1,304,1,99")?;
+        let mut strict = VM::new();
+        strict.load_program(&program)?;
+        assert!(matches!(strict.run(), Status::Exited(Err(_))));
+        let mut lenient = VM::new();
+        lenient.set_decoding(Decoding::Lenient);
+        lenient.load_program(&program)?;
+        assert!(matches!(lenient.run(), Status::HasOutput(304)));
+        Ok(())
+    }
+}
@@ -0,0 +1,199 @@
+//! Every error a `VM` returns is a `Fault` recording where it failed.
+
+use std::collections::VecDeque;
+use std::fmt::{Display, Error, Formatter};
+
+use crate::intcode::decoding::Decoding;
+use crate::intcode::extensions::InstructionSet;
+use crate::intcode::opcodes::OpCode;
+use crate::intcode::{Memory, Word};
+
+/// How far before the fault `window` starts decoding. Instruction
+/// boundaries can't be found by reading backwards, so the window decodes
+/// forwards from here and keeps the instructions that end up before the
+/// fault.
+const LOOKBEHIND: usize = 256;
+
+#[derive(Debug)]
+pub struct Fault<W = i64> {
+    /// Where the failing instruction starts.
+    pub ip: usize,
+    /// The words of the failing instruction, or the words decoding looked
+    /// at if they aren't a valid instruction.
+    pub words: Vec<W>,
+    pub code: Option<OpCode<W>>,
+    pub relative_base: W,
+    pub error: anyhow::Error,
+    instructions: InstructionSet<W>,
+    decoding: Decoding,
+}
+
+fn decode<W: Word, M: Memory<W>>(
+    memory: &M,
+    instructions: &InstructionSet<W>,
+    decoding: Decoding,
+    address: usize,
+) -> (Vec<W>, Option<OpCode<W>>) {
+    let mut words: Vec<W> = (address..address + 4)
+        .map(|address| memory.load(address).cloned().unwrap_or_default())
+        .collect();
+    let code = instructions.decode(&words, decoding).ok();
+    if let Some(code) = &code {
+        words.truncate(code.len());
+    }
+    (words, code)
+}
+
+fn line<W: Word>(marker: &str, address: usize, words: &[W], code: Option<&OpCode<W>>) -> String {
+    match code {
+        Some(code) => format!("{} {:04}\t\t{}", marker, address, code),
+        None => {
+            let words: Vec<String> = words.iter().map(W::to_string).collect();
+            format!("{} {:04}\t\t??\t\t{}", marker, address, words.join(","))
+        }
+    }
+}
+
+/// The line for the instruction at `address` and how many words it takes.
+/// Words that don't decode are shown one at a time.
+fn listing<W: Word, M: Memory<W>>(
+    memory: &M,
+    instructions: &InstructionSet<W>,
+    decoding: Decoding,
+    address: usize,
+) -> (String, usize) {
+    match decode(memory, instructions, decoding, address) {
+        (words, Some(code)) => (line("  ", address, &words, Some(&code)), code.len()),
+        (words, None) => (line("  ", address, &words[..1], None), 1),
+    }
+}
+
+impl<W: Word> Fault<W> {
+    pub(crate) fn new<M: Memory<W>>(
+        memory: &M,
+        instructions: &InstructionSet<W>,
+        decoding: Decoding,
+        ip: usize,
+        relative_base: W,
+        error: anyhow::Error,
+    ) -> Self {
+        let (words, code) = decode(memory, instructions, decoding, ip);
+        Self {
+            ip,
+            words,
+            code,
+            relative_base,
+            error,
+            instructions: instructions.clone(),
+            decoding,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.code.as_ref().map_or(1, OpCode::len)
+    }
+
+    /// Disassembles up to `context` instructions either side of the fault,
+    /// marking the failing one.
+    pub fn window<M: Memory<W>>(&self, memory: &M, context: usize) -> String {
+        let mut before = VecDeque::new();
+        let mut address = self.ip.saturating_sub(LOOKBEHIND);
+        while address < self.ip && context > 0 {
+            let (line, len) = listing(memory, &self.instructions, self.decoding, address);
+            if before.len() == context {
+                before.pop_front();
+            }
+            before.push_back(line);
+            address += len;
+        }
+        let mut lines: Vec<String> = before.into_iter().collect();
+        lines.push(line("=>", self.ip, &self.words, self.code.as_ref()));
+        let mut address = self.ip + self.len();
+        for _ in 0..context {
+            let (line, len) = listing(memory, &self.instructions, self.decoding, address);
+            lines.push(line);
+            address += len;
+        }
+        lines.join("\n")
+    }
+}
+
+impl<W: Word> Display for Fault<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(f, "{} at ip {}", self.error, self.ip)
+    }
+}
+
+impl<W: Word> std::error::Error for Fault<W> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.error.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::errors::ErrorKinds;
+    use crate::intcode::testing::vm;
+    use crate::intcode::{Executor, Runner, Status, VecPort};
+    use anyhow::Result;
+
+    #[test]
+    fn executor_errors_carry_the_fault() -> Result<()> {
+        let vm = vm("109,5,104,7,1101,9223372036854775807,1,0,99")?;
+        let mut executor = Executor::run(vm, VecPort::new());
+        assert_eq!(executor.next().transpose()?, Some(7));
+        let error = executor.next().expect("the add should fail").unwrap_err();
+        assert_eq!(error.to_string(), "arithmetic overflow at ip 4");
+        let fault = error.downcast_ref::<Fault>().expect("vm errors are faults");
+        assert_eq!(fault.ip, 4);
+        assert_eq!(fault.words, vec![1101, 9223372036854775807, 1, 0]);
+        assert_eq!(fault.code.as_ref().map(OpCode::len), Some(4));
+        assert_eq!(fault.relative_base, 5);
+        assert!(error.chain().any(|cause| matches!(
+            cause.downcast_ref::<ErrorKinds>(),
+            Some(ErrorKinds::ArithmeticOverflowError)
+        )));
+        assert_eq!(
+            fault.window(executor.vm(), 1),
+            [
+                "   0002\t\tout\t\t[ val:\ti0007 ].",
+                "=> 0004\t\tadd\t\t[ left:\ti9223372036854775807, right:\ti0001, out:\t&0000 ].",
+                "   0008\t\texit.",
+            ]
+            .join("\n")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_the_words_that_failed_to_decode() -> Result<()> {
+        let mut vm = vm("104,1,42,3,4,5")?;
+        assert!(matches!(vm.step(), Some(Status::HasOutput(1))));
+        let error = match vm.step() {
+            Some(Status::Exited(Err(error))) => error,
+            _ => panic!("42 isn't an opcode"),
+        };
+        let fault = error.downcast_ref::<Fault>().expect("vm errors are faults");
+        assert_eq!((fault.ip, fault.code.is_none()), (2, true));
+        assert_eq!(fault.words, vec![42, 3, 4, 5]);
+        assert!(fault.window(&vm, 2).contains("=> 0002\t\t??\t\t42,3,4,5"));
+        Ok(())
+    }
+
+    #[test]
+    fn input_faults_point_at_the_input_instruction() -> Result<()> {
+        let mut vm = vm("104,1,103,5,99")?;
+        assert!(matches!(vm.step(), Some(Status::HasOutput(1))));
+        assert!(matches!(vm.step(), Some(Status::RequiresInput)));
+        let error = vm.provide_input(3).unwrap_err();
+        assert!(matches!(
+            error.root_cause().downcast_ref::<ErrorKinds>(),
+            Some(ErrorKinds::ImmediateModeOutputError)
+        ));
+        let fault = error.downcast_ref::<Fault>().expect("vm errors are faults");
+        assert_eq!(fault.ip, 2);
+        assert_eq!(fault.words, vec![103, 5]);
+        Ok(())
+    }
+}
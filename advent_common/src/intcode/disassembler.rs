@@ -0,0 +1,240 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Error, Formatter};
+
+use crate::intcode::decoding::Decoding;
+use crate::intcode::extensions::InstructionSet;
+use crate::intcode::opcodes::OpCode;
+use crate::intcode::parameters::{ConditionParams, Parameter};
+use crate::intcode::Word;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum WordKind {
+    Code,
+    Data,
+    Unknown,
+}
+
+pub struct Disassembly<'a, W = i64> {
+    words: &'a [W],
+    kinds: Vec<WordKind>,
+    instructions: BTreeMap<usize, OpCode<W>>,
+    jump_targets: BTreeSet<usize>,
+}
+
+impl<'a, W: Word> Disassembly<'a, W> {
+    pub fn new(words: &'a [W]) -> Self {
+        Self::with_instructions(words, &InstructionSet::new())
+    }
+
+    /// Disassembles `words`, decoding the extensions in `instructions` too.
+    pub fn with_instructions(words: &'a [W], instructions: &InstructionSet<W>) -> Self {
+        let mut disassembly = Self {
+            words,
+            kinds: vec![WordKind::Unknown; words.len()],
+            instructions: BTreeMap::new(),
+            jump_targets: BTreeSet::new(),
+        };
+        disassembly.trace(instructions, 0);
+        disassembly.mark_data();
+        disassembly
+    }
+
+    fn trace(&mut self, instructions: &InstructionSet<W>, entry: usize) {
+        let mut pending = vec![entry];
+        while let Some(address) = pending.pop() {
+            if address >= self.words.len() || self.instructions.contains_key(&address) {
+                continue;
+            }
+            let code = match instructions.decode(&self.words[address..], Decoding::Strict) {
+                Ok(code) => code,
+                Err(_) => continue,
+            };
+            let next = address + code.len();
+            for kind in self.kinds[address..next].iter_mut() {
+                *kind = WordKind::Code;
+            }
+            match &code {
+                OpCode::Exit => {}
+                OpCode::JumpIfTrue(ConditionParams { test, location })
+                | OpCode::JumpIfFalse(ConditionParams { test, location }) => {
+                    let jumps_on = matches!(code, OpCode::JumpIfTrue(_));
+                    let (may_jump, may_fall_through) = match test {
+                        Parameter::Immediate(value) => {
+                            (value.is_zero() != jumps_on, value.is_zero() == jumps_on)
+                        }
+                        _ => (true, true),
+                    };
+                    if may_jump {
+                        if let Some(target) = match location {
+                            Parameter::Immediate(target) => target.to_usize(),
+                            _ => None,
+                        } {
+                            self.jump_targets.insert(target);
+                            pending.push(target);
+                        }
+                    }
+                    if may_fall_through {
+                        pending.push(next);
+                    }
+                }
+                _ => pending.push(next),
+            }
+            self.instructions.insert(address, code);
+        }
+    }
+
+    fn mark_data(&mut self) {
+        let mut references = Vec::new();
+        for code in self.instructions.values() {
+            for parameter in code.parameters() {
+                if let Parameter::Reference(address) = parameter {
+                    references.push(address);
+                }
+            }
+        }
+        for address in references {
+            if let Some(kind) = self.kinds.get_mut(address) {
+                if *kind == WordKind::Unknown {
+                    *kind = WordKind::Data;
+                }
+            }
+        }
+    }
+
+    pub fn kind(&self, address: usize) -> Option<WordKind> {
+        self.kinds.get(address).copied()
+    }
+
+    pub fn instruction(&self, address: usize) -> Option<&OpCode<W>> {
+        self.instructions.get(&address)
+    }
+
+    pub fn instructions(&self) -> impl Iterator<Item = (usize, &OpCode<W>)> {
+        self.instructions
+            .iter()
+            .map(|(&address, code)| (address, code))
+    }
+
+    pub fn is_jump_target(&self, address: usize) -> bool {
+        self.jump_targets.contains(&address)
+    }
+}
+
+fn label(address: usize) -> String {
+    format!("L{:04}", address)
+}
+
+impl<'a, W: Word> Display for Disassembly<'a, W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        let mut memory_location = 0;
+        let mut instruction_count = 0;
+        writeln!(f, "inst:mem \t\tcode\tparams")?;
+        writeln!(
+            f,
+            "------------------------------------------------------------------"
+        )?;
+        while memory_location < self.words.len() {
+            if self.is_jump_target(memory_location) {
+                writeln!(f, "{}:", label(memory_location))?;
+            }
+            write!(f, "{:04}:{:04}\t\t", instruction_count, memory_location)?;
+            match self.instruction(memory_location) {
+                Some(code) => {
+                    write!(f, "{}", code)?;
+                    if let OpCode::JumpIfTrue(ConditionParams {
+                        location: Parameter::Immediate(target),
+                        ..
+                    })
+                    | OpCode::JumpIfFalse(ConditionParams {
+                        location: Parameter::Immediate(target),
+                        ..
+                    }) = code
+                    {
+                        if let Some(target) = target.to_usize() {
+                            if self.is_jump_target(target) {
+                                write!(f, "\t-> {}", label(target))?;
+                            }
+                        }
+                    }
+                    writeln!(f)?;
+                    memory_location += code.len();
+                }
+                None => {
+                    let val = &self.words[memory_location];
+                    match self.kinds[memory_location] {
+                        WordKind::Data => writeln!(f, "data\t\t{}", val)?,
+                        _ => writeln!(f, "??\t\t{}", val)?,
+                    }
+                    memory_location += 1;
+                }
+            }
+            instruction_count += 1;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::Program;
+    use anyhow::Result;
+
+    #[test]
+    fn classifies_words() -> Result<()> {
+        let program = Program::from_assembly(
+            "
+                    jt      i1, istart
+            table:  data    1, 2, 3, 99
+            start:  out     &table
+                    exit
+            spare:  data    1101
+            ",
+        )?;
+        let disassembly = Disassembly::new(program.as_inner());
+        let kinds: Vec<WordKind> = (0..program.as_inner().len())
+            .filter_map(|address| disassembly.kind(address))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                WordKind::Code,
+                WordKind::Code,
+                WordKind::Code,
+                WordKind::Data,
+                WordKind::Unknown,
+                WordKind::Unknown,
+                WordKind::Unknown,
+                WordKind::Code,
+                WordKind::Code,
+                WordKind::Code,
+                WordKind::Unknown,
+            ],
+            "only words reached from address 0 should be code"
+        );
+        assert!(disassembly.is_jump_target(7));
+        assert!(disassembly.instruction(3).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn follows_both_branches_of_conditional_jumps() -> Result<()> {
+        let program = Program::from_assembly(
+            "
+                    in      &n
+                    jf      &n, iskip
+                    out     &n
+            skip:   exit
+            n:      data    0
+            ",
+        )?;
+        let disassembly = Disassembly::new(program.as_inner());
+        let starts: Vec<usize> = disassembly
+            .instructions()
+            .map(|(address, _)| address)
+            .collect();
+        assert_eq!(starts, vec![0, 2, 5, 7]);
+        assert!(disassembly.to_string().contains("L0007:"));
+        Ok(())
+    }
+}
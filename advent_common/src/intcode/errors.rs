@@ -12,6 +12,7 @@ pub enum OutOfBoundsReference {
     OpCodeLength,
 }
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Error)]
 pub enum IOError {
     #[error("couldn't read from input {0}")]
@@ -22,6 +23,88 @@ pub enum IOError {
     StringParseError(String),
     #[error("out of static input")]
     OutOfStaticInputError,
+    #[error("the channel feeding this port was closed")]
+    ChannelClosedError,
+    #[error("no input is available yet")]
+    InputPendingError,
+}
+
+#[derive(Debug, Error)]
+pub enum AssemblyError {
+    #[error("unknown mnemonic {0}")]
+    UnknownMnemonic(String),
+    #[error("{mnemonic} expects {expected} parameters, found {found}")]
+    WrongParameterCount {
+        mnemonic: String,
+        expected: usize,
+        found: usize,
+    },
+    #[error("invalid parameter {0}")]
+    InvalidParameter(String),
+    #[error("invalid label {0}")]
+    InvalidLabel(String),
+    #[error("label {0} is defined more than once")]
+    DuplicateLabel(String),
+    #[error("undefined label {0}")]
+    UndefinedLabel(String),
+    #[error("output parameter {0} is in immediate mode")]
+    ImmediateModeOutput(String),
+    #[error("reference parameter {0} is less than zero")]
+    ReferenceLessThanZero(String),
+}
+
+#[derive(Debug, Error)]
+pub enum CompileError {
+    #[error("unexpected character {0}")]
+    UnexpectedCharacter(char),
+    #[error("invalid number {0}")]
+    InvalidNumber(String),
+    #[error("expected {expected}, found {found}")]
+    UnexpectedToken { expected: String, found: String },
+    #[error("expected {0}, found the end of the program")]
+    UnexpectedEnd(String),
+    #[error("undefined variable {0}")]
+    UndefinedVariable(String),
+    #[error("undefined function {0}")]
+    UndefinedFunction(String),
+    #[error("function {0} is defined more than once")]
+    DuplicateFunction(String),
+    #[error("parameter {0} is declared more than once")]
+    DuplicateParameter(String),
+    #[error("{name} expects {expected} arguments, found {found}")]
+    WrongArgumentCount {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+}
+
+#[derive(Debug, Error)]
+pub enum DecodeError {
+    #[error("mode {mode} of parameter {parameter} in {instruction} isn't 0, 1 or 2")]
+    InvalidMode {
+        instruction: i64,
+        parameter: usize,
+        mode: i64,
+    },
+    #[error("{instruction} has mode digits past its {parameters} parameters")]
+    UnusedModes { instruction: i64, parameters: usize },
+    #[error("instruction {0} is negative")]
+    NegativeInstruction(i64),
+}
+
+#[derive(Debug, Error)]
+pub enum ExtensionError {
+    #[error("opcode {0} isn't between 0 and 99")]
+    InvalidOpcode(i64),
+    #[error("opcode {0} is already part of the instruction set")]
+    BuiltinOpcode(i64),
+    #[error("opcode {0} is already registered")]
+    DuplicateOpcode(i64),
+    #[error("{0} takes more than three parameters")]
+    TooManyParameters(String),
+    #[error("{0} writes more than one parameter")]
+    MultipleWrites(String),
 }
 
 #[derive(Error, Debug)]
@@ -35,7 +118,11 @@ pub enum ErrorKinds {
     #[error("out of bound reference {0}")]
     MemoryError(OutOfBoundsReference),
     #[error("parse error: unknown opcode {0}")]
-    UnknownOpcodeError(i64),
+    UnknownOpcodeError(String),
+    #[error("decode error: {0}")]
+    DecodeError(DecodeError),
+    #[error("extension error: {0}")]
+    ExtensionError(ExtensionError),
     #[error("io error: {0}")]
     IOError(IOError),
     #[error("output parameter was in immediate mode")]
@@ -44,8 +131,16 @@ pub enum ErrorKinds {
     UnexpectedInputError,
     #[error("no input provided to a vm which was expecting input")]
     ExpectedInputError,
-    #[error("not enough memory to load program")]
-    NotEnoughMemoryToLoadProgramError,
     #[error("reference less than zero")]
     ReferenceLessThanZeroError,
+    #[error("assembly error on line {0}: {1}")]
+    AssemblyError(usize, AssemblyError),
+    #[error("compile error on line {0}: {1}")]
+    CompileError(usize, CompileError),
+    #[error("arithmetic overflow")]
+    ArithmeticOverflowError,
+    #[error("the instruction budget was used up")]
+    OutOfFuelError,
+    #[error("invalid snapshot: {0}")]
+    InvalidSnapshotError(String),
 }
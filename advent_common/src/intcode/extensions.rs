@@ -0,0 +1,302 @@
+//! Opcodes registered at run time on top of the puzzle's instruction set.
+
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use crate::intcode::decoding::{self, Decoding};
+use crate::intcode::errors::{ErrorKinds, ExtensionError};
+use crate::intcode::opcodes::OpCode;
+use crate::intcode::parameters::Parameter;
+use crate::intcode::{VMType, Word};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Role {
+    Read,
+    Write,
+}
+
+/// Runs an extension. The vm moves past the instruction afterwards, so
+/// handlers can't jump.
+pub type Handler<W> = dyn Fn(&mut dyn VMType<W>, &[Parameter<W>]) -> Result<()> + Send + Sync;
+
+pub struct Extension<W = i64> {
+    pub opcode: i64,
+    pub name: String,
+    pub roles: Vec<Role>,
+    handler: Box<Handler<W>>,
+}
+
+impl<W> Extension<W> {
+    pub(crate) fn exec(&self, vm: &mut dyn VMType<W>, parameters: &[Parameter<W>]) -> Result<()> {
+        (self.handler)(vm, parameters)
+    }
+}
+
+impl<W> Debug for Extension<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Extension")
+            .field("opcode", &self.opcode)
+            .field("name", &self.name)
+            .field("roles", &self.roles)
+            .finish()
+    }
+}
+
+impl<W> PartialEq for Extension<W> {
+    fn eq(&self, other: &Self) -> bool {
+        self.opcode == other.opcode && self.name == other.name && self.roles == other.roles
+    }
+}
+
+impl<W> Eq for Extension<W> {}
+
+/// A decoded extension instruction.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ExtensionParams<W = i64> {
+    pub extension: Arc<Extension<W>>,
+    pub parameters: Vec<Parameter<W>>,
+}
+
+impl<W: Display> Display for ExtensionParams<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let parameters: Vec<String> = self
+            .extension
+            .roles
+            .iter()
+            .zip(self.parameters.iter())
+            .map(|(role, parameter)| match role {
+                Role::Read => format!("read:\t{}", parameter),
+                Role::Write => format!("write:\t{}", parameter),
+            })
+            .collect();
+        if parameters.is_empty() {
+            write!(f, "[ ]")
+        } else {
+            write!(f, "[ {} ]", parameters.join(", "))
+        }
+    }
+}
+
+/// The extensions a vm or disassembly decodes alongside the puzzle's
+/// opcodes.
+#[derive(Debug, Clone)]
+pub struct InstructionSet<W = i64> {
+    extensions: HashMap<i64, Arc<Extension<W>>>,
+}
+
+impl<W> Default for InstructionSet<W> {
+    fn default() -> Self {
+        Self {
+            extensions: HashMap::new(),
+        }
+    }
+}
+
+impl<W: Word> InstructionSet<W> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn register<F>(
+        &mut self,
+        opcode: i64,
+        name: &str,
+        roles: &[Role],
+        handler: F,
+    ) -> Result<&mut Self>
+    where
+        F: Fn(&mut dyn VMType<W>, &[Parameter<W>]) -> Result<()> + Send + Sync + 'static,
+    {
+        let error = |e| -> anyhow::Error { ErrorKinds::ExtensionError(e).into() };
+        if !(0..100).contains(&opcode) {
+            return Err(error(ExtensionError::InvalidOpcode(opcode)));
+        }
+        if decoding::arity(opcode).is_some() {
+            return Err(error(ExtensionError::BuiltinOpcode(opcode)));
+        }
+        if self.extensions.contains_key(&opcode) {
+            return Err(error(ExtensionError::DuplicateOpcode(opcode)));
+        }
+        if roles.len() > 3 {
+            return Err(error(ExtensionError::TooManyParameters(name.to_owned())));
+        }
+        if roles.iter().filter(|&&role| role == Role::Write).count() > 1 {
+            return Err(error(ExtensionError::MultipleWrites(name.to_owned())));
+        }
+        let extension = Extension {
+            opcode,
+            name: name.to_owned(),
+            roles: roles.to_vec(),
+            handler: Box::new(handler),
+        };
+        self.extensions.insert(opcode, Arc::new(extension));
+        Ok(self)
+    }
+
+    pub fn get(&self, opcode: i64) -> Option<&Extension<W>> {
+        self.extensions.get(&opcode).map(Arc::as_ref)
+    }
+
+    fn arity(&self, opcode: i64) -> Option<usize> {
+        match self.extensions.get(&opcode) {
+            Some(extension) => Some(extension.roles.len()),
+            None => decoding::arity(opcode),
+        }
+    }
+
+    /// Decodes the instruction at the start of `instructions`, trying the
+    /// registered extensions before the puzzle's opcodes.
+    pub fn decode(&self, instructions: &[W], decoding: Decoding) -> Result<OpCode<W>> {
+        let value = match instructions.first().and_then(W::to_i64) {
+            Some(value) if !self.extensions.is_empty() => value,
+            _ => return OpCode::decode(instructions, decoding),
+        };
+        let (opcode, modes) = decoding.split_with(value, |opcode| self.arity(opcode))?;
+        let extension = match self.extensions.get(&opcode) {
+            Some(extension) => extension,
+            None => return OpCode::decode(instructions, decoding),
+        };
+        let parameters = (0..extension.roles.len())
+            .map(|idx| Parameter::new(idx + 1, modes[2 - idx], instructions))
+            .collect::<Result<_>>()?;
+        Ok(OpCode::Extension(ExtensionParams {
+            extension: extension.clone(),
+            parameters,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::disassembler::Disassembly;
+    use crate::intcode::{Execution, Executor, Memory, Observer, Program, Runner, VecPort, VM};
+    use std::sync::Mutex;
+
+    fn instructions(log: Arc<Mutex<Vec<i64>>>) -> Result<InstructionSet> {
+        let mut instructions = InstructionSet::new();
+        instructions
+            .register(
+                20,
+                "dbg",
+                &[Role::Read],
+                move |vm: &mut dyn VMType, parameters: &[Parameter]| {
+                    let value = parameters[0].read(vm)?;
+                    log.lock().expect("the log isn't poisoned").push(value);
+                    Ok(())
+                },
+            )?
+            .register(
+                21,
+                "sq",
+                &[Role::Read, Role::Write],
+                |vm: &mut dyn VMType, parameters: &[Parameter]| {
+                    let value = parameters[0].read(vm)?;
+                    let square = vm
+                        .overflow_policy()
+                        .mul(&value, &value)
+                        .ok_or(ErrorKinds::ArithmeticOverflowError)?;
+                    *parameters[1].read_mut(vm)? = square;
+                    Ok(())
+                },
+            )?;
+        Ok(instructions)
+    }
+
+    #[test]
+    fn runs_registered_opcodes() -> Result<()> {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut vm = VM::new();
+        vm.set_instruction_set(instructions(log.clone())?);
+        vm.load_program(&Program::from_source("120,7,121,7,10,20,10,4,10,99,0")?)?;
+        let output = Executor::run(vm, VecPort::new()).collect::<Result<Vec<_>>>()?;
+        assert_eq!(output, vec![49]);
+        assert_eq!(*log.lock().expect("the log isn't poisoned"), vec![7, 49]);
+        Ok(())
+    }
+
+    #[derive(Default)]
+    struct Writes(Vec<(usize, i64)>);
+
+    impl Observer for Writes {
+        fn on_instruction(&mut self, execution: &Execution) {
+            self.0.extend_from_slice(execution.writes);
+        }
+    }
+
+    #[test]
+    fn records_every_word_a_handler_writes() -> Result<()> {
+        let mut instructions = InstructionSet::new();
+        instructions.register(
+            22,
+            "mark",
+            &[Role::Write],
+            |vm: &mut dyn VMType, parameters: &[Parameter]| {
+                *parameters[0].read_mut(vm)? = 1;
+                if let Some(word) = vm.load_mut(10) {
+                    *word = 77;
+                }
+                Ok(())
+            },
+        )?;
+        let mut vm = VM::new();
+        vm.set_instruction_set(instructions);
+        vm.load_program(&Program::from_source("22,9,99")?)?;
+        vm.record_history(4);
+        let writes = Arc::new(Mutex::new(Writes::default()));
+        vm.attach_observer(writes.clone());
+        assert!(vm.step().is_none());
+        assert_eq!(
+            writes.lock().expect("the observer isn't poisoned").0,
+            vec![(9, 1), (10, 77)]
+        );
+        assert_eq!((vm.load(9), vm.load(10)), (Some(&1), Some(&77)));
+        assert!(vm.step_back());
+        assert_eq!((vm.load(9), vm.load(10)), (Some(&0), Some(&0)));
+        assert_eq!(vm.ip(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn disassembles_registered_opcodes() -> Result<()> {
+        let instructions = instructions(Arc::new(Mutex::new(Vec::new())))?;
+        let words = Program::from_source("121,7,9,20,9,99")?.load();
+        let disassembly = Disassembly::with_instructions(&words, &instructions);
+        let code = disassembly.instruction(0).expect("sq should decode");
+        assert_eq!(code.to_string(), "sq\t\t[ read:\ti0007, write:\t&0009 ].");
+        assert_eq!(code.encode(), &words[..3]);
+        let code = disassembly.instruction(3).expect("dbg should decode");
+        assert_eq!(code.to_string(), "dbg\t\t[ read:\t&0009 ].");
+        assert!(Disassembly::new(&words).instruction(0).is_none());
+        assert!(instructions.decode(&[1120, 0], Decoding::Strict).is_err());
+        assert!(instructions.decode(&[1120, 0], Decoding::Lenient).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_clashing_registrations() -> Result<()> {
+        let mut instructions = instructions(Arc::new(Mutex::new(Vec::new())))?;
+        let nothing = |_: &mut dyn VMType, _: &[Parameter]| Ok(());
+        let error = |result: Result<&mut InstructionSet>| result.unwrap_err().to_string();
+        assert_eq!(
+            error(instructions.register(1, "add", &[], nothing)),
+            "extension error: opcode 1 is already part of the instruction set"
+        );
+        assert_eq!(
+            error(instructions.register(20, "dbg2", &[], nothing)),
+            "extension error: opcode 20 is already registered"
+        );
+        assert_eq!(
+            error(instructions.register(100, "big", &[], nothing)),
+            "extension error: opcode 100 isn't between 0 and 99"
+        );
+        assert_eq!(
+            error(instructions.register(30, "swap", &[Role::Write, Role::Write], nothing)),
+            "extension error: swap writes more than one parameter"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::VecDeque;
+
+use crate::intcode::vm::InternalStatus;
+
+#[derive(Clone, Debug)]
+pub(crate) struct Change<W = i64> {
+    pub instruction_pointer: usize,
+    pub relative_base: W,
+    pub status: InternalStatus<W>,
+    /// The old value of every word the step wrote.
+    pub writes: Vec<(usize, W)>,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct History<W = i64> {
+    changes: VecDeque<Change<W>>,
+    max_steps: usize,
+}
+
+impl<W> History<W> {
+    pub fn new(max_steps: usize) -> Self {
+        Self {
+            changes: VecDeque::new(),
+            max_steps,
+        }
+    }
+
+    pub fn push(&mut self, change: Change<W>) {
+        if self.max_steps == 0 {
+            return;
+        }
+        if self.changes.len() == self.max_steps {
+            self.changes.pop_front();
+        }
+        self.changes.push_back(change);
+    }
+
+    pub fn pop(&mut self) -> Option<Change<W>> {
+        self.changes.pop_back()
+    }
+
+    /// How many changes have to be undone to get back to the latest state
+    /// whose instruction pointer was `address`.
+    pub fn steps_back_to(&self, address: usize) -> Option<usize> {
+        self.changes
+            .iter()
+            .rev()
+            .position(|change| change.instruction_pointer == address)
+            .map(|idx| idx + 1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.changes.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intcode::vm::InternalStatus;
+    use crate::intcode::{Memory, Program, Runable, Status, VMType, VM};
+    use anyhow::Result;
+
+    fn state(vm: &VM) -> (String, usize, i64, InternalStatus) {
+        (vm.dump(), vm.ip(), vm.relative_base(), vm.status.clone())
+    }
+
+    #[test]
+    fn steps_back_through_every_state() -> Result<()> {
+        let program =
+            Program::from_source("109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99")?;
+        let mut vm = VM::new();
+        vm.load_program(&program)?;
+        vm.record_history(usize::MAX);
+        let mut states = vec![state(&vm)];
+        loop {
+            match vm.step() {
+                Some(Status::Exited(result)) => {
+                    result?;
+                    break;
+                }
+                Some(Status::RequiresInput) => panic!("the program doesn't read input"),
+                _ => states.push(state(&vm)),
+            }
+        }
+        states.push(state(&vm));
+        assert_eq!(vm.history_len(), states.len() - 1);
+        while let Some(expected) = states.pop() {
+            assert!(
+                state(&vm) == expected,
+                "state should match at ip {}",
+                expected.1
+            );
+            if !states.is_empty() {
+                assert!(vm.step_back());
+            }
+        }
+        assert!(!vm.step_back(), "there should be nothing left to undo");
+        Ok(())
+    }
+
+    #[test]
+    fn undoes_input() -> Result<()> {
+        let program = Program::from_source("3,9,1002,9,2,9,4,9,99,0")?;
+        let mut vm = VM::new();
+        vm.load_program(&program)?;
+        vm.record_history(16);
+        assert!(matches!(vm.run(), Status::RequiresInput));
+        assert!(matches!(vm.run_with_input(4), Status::HasOutput(8)));
+        assert!(vm.run_back_to(2));
+        assert_eq!(vm.load(9), Some(&4));
+        assert!(vm.step_back());
+        assert_eq!(vm.load(9), Some(&0));
+        assert!(matches!(vm.run_with_input(5), Status::HasOutput(10)));
+        Ok(())
+    }
+
+    #[test]
+    fn leaves_the_vm_alone_when_the_address_was_never_reached() -> Result<()> {
+        let program = Program::from_source("3,9,1002,9,2,9,4,9,99,0")?;
+        let mut vm = VM::new();
+        vm.load_program(&program)?;
+        vm.record_history(16);
+        assert!(matches!(vm.run(), Status::RequiresInput));
+        assert!(matches!(vm.run_with_input(4), Status::HasOutput(8)));
+        let before = state(&vm);
+        let recorded = vm.history_len();
+        assert!(!vm.run_back_to(5));
+        assert!(state(&vm) == before, "the vm should be untouched");
+        assert_eq!(vm.history_len(), recorded);
+        assert!(vm.run_back_to(0));
+        assert_eq!(vm.load(9), Some(&0));
+        Ok(())
+    }
+
+    #[test]
+    fn caps_the_log() -> Result<()> {
+        let program = Program::from_source("1101,0,0,7,1105,1,0,0")?;
+        let mut vm = VM::new();
+        vm.load_program(&program)?;
+        vm.record_history(3);
+        for _ in 0..10 {
+            assert!(vm.step().is_none());
+        }
+        assert_eq!(vm.history_len(), 3);
+        assert!(vm.step_back() && vm.step_back() && vm.step_back());
+        assert!(!vm.step_back());
+        Ok(())
+    }
+}
@@ -1,61 +1,172 @@
 use super::Memory as TMemory;
+use super::Word;
 use std::collections::HashMap;
 
-const BUFFER_SIZE: usize = 4096;
+pub const PAGE_SIZE: usize = 4096;
+const DENSE_PAGES: usize = 256;
+/// Where the pages kept in a map instead of a vector start.
+pub const SPARSE_START: usize = DENSE_PAGES * PAGE_SIZE;
+
+type Page<W> = Box<[W]>;
+
+fn new_page<W: Word>() -> Page<W> {
+    vec![W::default(); PAGE_SIZE].into_boxed_slice()
+}
 
 #[derive(Clone)]
-pub struct Memory {
-    buf: Box<[i64; BUFFER_SIZE]>,
-    large_address_storage: HashMap<usize, i64>,
+pub struct Memory<W = i64> {
+    pages: Vec<Option<Page<W>>>,
+    sparse_pages: HashMap<usize, Page<W>>,
+    zero: W,
 }
 
-impl Memory {
+impl<W: Word> Memory<W> {
     pub fn new() -> Self {
         Self {
-            buf: Box::new([0; BUFFER_SIZE]),
-            large_address_storage: HashMap::new(),
+            pages: Vec::new(),
+            sparse_pages: HashMap::new(),
+            zero: W::default(),
         }
     }
 
     pub fn zero(&mut self) -> &mut Self {
-        for address in self.iter_mut() {
-            *address = 0;
-        }
-        self.large_address_storage.clear();
+        self.pages.clear();
+        self.sparse_pages.clear();
         self
     }
 
-    pub fn as_inner(&self) -> &[i64; BUFFER_SIZE] {
-        self.buf.as_ref()
+    pub fn write_slice(&mut self, start: usize, words: &[W]) -> &mut Self {
+        for (idx, word) in words.iter().enumerate() {
+            *self.word_mut(start + idx) = word.clone();
+        }
+        self
     }
 
-    pub fn as_inner_mut(&mut self) -> &mut [i64; BUFFER_SIZE] {
-        self.buf.as_mut()
+    pub fn read_into(&self, start: usize, output: &mut [W]) {
+        for (idx, word) in output.iter_mut().enumerate() {
+            *word = self.word(start + idx).clone();
+        }
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = &i64> {
-        self.buf.iter()
+    /// Every address up to the end of the last dense page written, then the
+    /// words of each sparse page, in address order.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &W)> + '_ {
+        let dense = self
+            .pages
+            .iter()
+            .enumerate()
+            .flat_map(move |(page, words)| {
+                (0..PAGE_SIZE).map(move |offset| {
+                    let word = words.as_ref().map_or(&self.zero, |words| &words[offset]);
+                    (page * PAGE_SIZE + offset, word)
+                })
+            });
+        let mut sparse: Vec<(&usize, &Page<W>)> = self.sparse_pages.iter().collect();
+        sparse.sort_unstable_by_key(|&(&page, _)| page);
+        let sparse = sparse.into_iter().flat_map(|(&page, words)| {
+            words
+                .iter()
+                .enumerate()
+                .map(move |(offset, word)| (page * PAGE_SIZE + offset, word))
+        });
+        dense.chain(sparse)
     }
 
-    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut i64> {
-        self.buf.iter_mut()
+    fn word(&self, idx: usize) -> &W {
+        let (page, offset) = (idx / PAGE_SIZE, idx % PAGE_SIZE);
+        if page < DENSE_PAGES {
+            self.pages
+                .get(page)
+                .and_then(Option::as_ref)
+                .map_or(&self.zero, |words| &words[offset])
+        } else {
+            self.sparse_pages
+                .get(&page)
+                .map_or(&self.zero, |words| &words[offset])
+        }
     }
-}
 
-impl TMemory for Memory {
-    fn load(&self, idx: usize) -> Option<&i64> {
-        if idx >= BUFFER_SIZE {
-            self.large_address_storage.get(&idx).or(Some(&0))
+    fn word_mut(&mut self, idx: usize) -> &mut W {
+        let (page, offset) = (idx / PAGE_SIZE, idx % PAGE_SIZE);
+        if page < DENSE_PAGES {
+            if self.pages.len() <= page {
+                self.pages.resize_with(page + 1, || None);
+            }
+            &mut self.pages[page].get_or_insert_with(new_page)[offset]
         } else {
-            self.buf.get(idx)
+            &mut self.sparse_pages.entry(page).or_insert_with(new_page)[offset]
         }
     }
+}
 
-    fn load_mut(&mut self, idx: usize) -> Option<&mut i64> {
-        if idx >= BUFFER_SIZE {
-            Some(self.large_address_storage.entry(idx).or_insert(0))
-        } else {
-            self.buf.get_mut(idx)
+impl<W: Word> TMemory<W> for Memory<W> {
+    fn load(&self, idx: usize) -> Option<&W> {
+        Some(self.word(idx))
+    }
+
+    fn load_mut(&mut self, idx: usize) -> Option<&mut W> {
+        Some(self.word_mut(idx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{Executor, Program, Runable, Runner, Status, VMType, VecPort, VM};
+    use anyhow::Result;
+
+    #[test]
+    fn reads_and_writes_across_pages() {
+        let mut memory: Memory = Memory::new();
+        assert_eq!(memory.load(1 << 40), Some(&0));
+        let words: Vec<i64> = (0..10).collect();
+        memory.write_slice(PAGE_SIZE - 5, &words);
+        memory.write_slice(DENSE_PAGES * PAGE_SIZE - 5, &words);
+        *memory.load_mut(1 << 40).unwrap() = 7;
+        let mut read = [0; 10];
+        memory.read_into(PAGE_SIZE - 5, &mut read);
+        assert_eq!(&read[..], &words[..]);
+        memory.read_into(DENSE_PAGES * PAGE_SIZE - 5, &mut read);
+        assert_eq!(&read[..], &words[..]);
+        assert_eq!(memory.load(1 << 40), Some(&7));
+    }
+
+    #[test]
+    fn allocates_only_the_pages_written() {
+        let mut memory: Memory = Memory::new();
+        *memory.load_mut(SPARSE_START - 1).unwrap() = 3;
+        *memory.load_mut(1 << 40).unwrap() = 7;
+        assert_eq!(memory.pages.iter().flatten().count(), 1);
+        let words: Vec<(usize, i64)> = memory
+            .iter()
+            .filter(|(_, word)| **word != 0)
+            .map(|(address, word)| (address, *word))
+            .collect();
+        assert_eq!(words, vec![(SPARSE_START - 1, 3), (1 << 40, 7)]);
+        assert_eq!(memory.iter().count(), SPARSE_START + PAGE_SIZE);
+    }
+
+    #[test]
+    fn dumps_sparse_words() -> Result<()> {
+        let mut vm = VM::new();
+        vm.load_program(&Program::from_source("1101,2,3,2000000,99")?)?;
+        assert!(matches!(vm.run(), Status::Exited(Ok(()))));
+        assert!(vm.dump().starts_with("1101,2,3,2000000,99,0,"));
+        assert!(vm.dump().ends_with(",0\n2000000: 5"));
+        Ok(())
+    }
+
+    #[test]
+    fn runs_code_at_any_address() -> Result<()> {
+        for &address in &[PAGE_SIZE - 1, 10_000, DENSE_PAGES * PAGE_SIZE - 2] {
+            let mut words = vec![0; address + 3];
+            words[..3].copy_from_slice(&[1105, 1, address as i64]);
+            words[address..].copy_from_slice(&[104, 42, 99]);
+            let mut vm = VM::new();
+            vm.load_program(&Program::from(words))?;
+            let output: Vec<i64> = Executor::run(vm, VecPort::new()).collect::<Result<_>>()?;
+            assert_eq!(output, vec![42], "code at {} should run", address);
         }
+        Ok(())
     }
 }
@@ -1,38 +1,69 @@
+pub mod analysis;
+pub mod ascii;
+pub mod assembler;
+mod cache;
+pub mod compiler;
+pub mod decoding;
+pub mod diagnostics;
+pub mod disassembler;
 mod errors;
+pub mod extensions;
+mod history;
 mod memory;
-mod opcodes;
-mod parameters;
+pub mod network;
+pub mod observer;
+pub mod opcodes;
+pub mod optimizer;
+pub mod overflow;
+pub mod parameters;
 pub mod ports;
 pub mod program;
+pub mod snapshot;
 pub mod status;
+#[cfg(test)]
+pub(crate) mod testing;
 pub mod vm;
-
-use crate::intcode::parameters::Parameter;
-pub use ports::{Port, VecPort};
+pub mod word;
+
+use std::marker::PhantomData;
+
+use crate::intcode::errors::ErrorKinds;
+pub use analysis::{Analysis, JumpTarget};
+pub use decoding::Decoding;
+pub use diagnostics::Fault;
+pub use extensions::{InstructionSet, Role};
+pub use network::{Network, Topology};
+pub use observer::{Execution, Observer, SharedObserver};
+pub use opcodes::OpCode;
+pub use overflow::OverflowPolicy;
+pub use parameters::Parameter;
+pub use ports::{ChannelPort, EmptyInput, Format, Port, VecPort};
 pub use program::Program;
 pub use status::Status;
 pub use vm::VM;
+pub use word::Word;
 
-pub trait ReadInt {
-    fn read_int(&mut self) -> anyhow::Result<i64>;
+pub trait ReadInt<W = i64> {
+    fn read_int(&mut self) -> anyhow::Result<W>;
 }
 
-pub trait WriteInt {
-    fn write_int(&mut self, i: i64) -> anyhow::Result<()>;
+pub trait WriteInt<W = i64> {
+    fn write_int(&mut self, i: W) -> anyhow::Result<()>;
 }
 
-pub trait PortType: ReadInt + WriteInt {}
+pub trait PortType<W = i64>: ReadInt<W> + WriteInt<W> {}
 
-impl<T: ReadInt + WriteInt> PortType for T {}
+impl<W, T: ReadInt<W> + WriteInt<W>> PortType<W> for T {}
 
-pub trait Runable {
-    fn run_with_input(&mut self, input: i64) -> Status;
-    fn run(&mut self) -> Status;
+pub trait Runable<W = i64> {
+    fn run_with_input(&mut self, input: W) -> Status<W>;
+    fn run(&mut self) -> Status<W>;
+    fn run_for(&mut self, steps: u64) -> Status<W>;
 }
 
-pub trait Runner: Iterator<Item = anyhow::Result<i64>> {
-    type VM: VMType;
-    type Port: PortType;
+pub trait Runner<W: Word = i64>: Iterator<Item = anyhow::Result<W>> {
+    type VM: VMType<W>;
+    type Port: PortType<W>;
 
     fn run(vm: Self::VM, port: Self::Port) -> Self;
 
@@ -43,48 +74,81 @@ pub trait Runner: Iterator<Item = anyhow::Result<i64>> {
     fn vm_mut(&mut self) -> &mut Self::VM;
 }
 
-pub struct Executor<V: VMType, P: PortType> {
+pub struct Executor<V: VMType<W>, P: PortType<W>, W: Word = i64> {
     v: V,
     p: P,
+    waiting: bool,
+    word: PhantomData<W>,
 }
 
-impl<V: VMType, P: PortType> Executor<V, P> {
-    fn on_exit(&mut self, status: Status) -> Option<anyhow::Result<i64>> {
+impl<V: VMType<W>, P: PortType<W>, W: Word> Executor<V, P, W> {
+    pub fn with_budget(mut vm: V, port: P, budget: u64) -> Self {
+        vm.set_fuel(Some(budget));
+        Self::run(vm, port)
+    }
+
+    /// Whether the port yielded instead of supplying the input the vm asked
+    /// for. The iterator reports that as an error item, which
+    /// `ports::is_input_pending` recognises, and picks up where it left off
+    /// once the port has more.
+    pub fn is_waiting_for_input(&self) -> bool {
+        self.waiting
+    }
+
+    fn read_input(&mut self) -> Option<anyhow::Result<W>> {
+        match self.p.read_int() {
+            Ok(input) => {
+                self.waiting = false;
+                let status = self.v.run_with_input(input);
+                self.on_exit(status)
+            }
+            Err(e) if ports::is_input_pending(&e) => {
+                self.waiting = true;
+                Some(Err(e))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+
+    fn on_exit(&mut self, status: Status<W>) -> Option<anyhow::Result<W>> {
         match status {
             Status::Exited(e) => e.err().map(Err),
             Status::HasOutput(out) => {
-                if let Some(e) = self.p.write_int(out).err() {
+                if let Some(e) = self.p.write_int(out.clone()).err() {
                     Some(Err(e))
                 } else {
                     Some(Ok(out))
                 }
             }
-            Status::RequiresInput => match self.p.read_int() {
-                Ok(input) => {
-                    let status = self.v.run_with_input(input);
-                    self.on_exit(status)
-                }
-                err => Some(err),
-            },
+            Status::RequiresInput => self.read_input(),
+            Status::Yielded => Some(Err(ErrorKinds::OutOfFuelError.into())),
         }
     }
 }
 
-impl<V: VMType, P: PortType> Iterator for Executor<V, P> {
-    type Item = anyhow::Result<i64>;
+impl<V: VMType<W>, P: PortType<W>, W: Word> Iterator for Executor<V, P, W> {
+    type Item = anyhow::Result<W>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if self.waiting {
+            return self.read_input();
+        }
         let status = self.v.run();
         self.on_exit(status)
     }
 }
 
-impl<V: VMType, P: PortType> Runner for Executor<V, P> {
+impl<V: VMType<W>, P: PortType<W>, W: Word> Runner<W> for Executor<V, P, W> {
     type VM = V;
     type Port = P;
 
     fn run(vm: Self::VM, port: Self::Port) -> Self {
-        Self { v: vm, p: port }
+        Self {
+            v: vm,
+            p: port,
+            waiting: false,
+            word: PhantomData,
+        }
     }
 
     fn port(&self) -> &Self::Port {
@@ -102,20 +166,28 @@ impl<V: VMType, P: PortType> Runner for Executor<V, P> {
     }
 }
 
-pub trait Memory {
-    fn load(&self, idx: usize) -> Option<&i64>;
-    fn load_mut(&mut self, idx: usize) -> Option<&mut i64>;
+pub trait Memory<W = i64> {
+    fn load(&self, idx: usize) -> Option<&W>;
+    fn load_mut(&mut self, idx: usize) -> Option<&mut W>;
 }
 
-pub trait VMType: Runable + Memory {
-    fn input_to(&mut self, location: Parameter);
-    fn output(&mut self, output: i64);
-    fn load_program(&mut self, program: &Program) -> anyhow::Result<()>;
+pub trait VMType<W: Word = i64>: Runable<W> + Memory<W> {
+    fn input_to(&mut self, location: Parameter<W>);
+    fn output(&mut self, output: W);
+    fn load_program(&mut self, program: &Program<W>) -> anyhow::Result<()>;
     fn ip(&self) -> usize;
-    fn offset_relative_base(&mut self, base: i64);
-    fn load_rel(&self, idx: i64) -> Option<&i64>;
-    fn load_rel_mut(&mut self, idx: i64) -> Option<&mut i64>;
-    fn advance(&mut self, amount: usize) -> &mut Self;
-    fn jump_to(&mut self, to: usize) -> &mut Self;
+    fn offset_relative_base(&mut self, base: W) -> anyhow::Result<()>;
+    fn relative_address(&self, idx: W) -> anyhow::Result<usize>;
+    fn load_rel(&self, idx: W) -> Option<&W>;
+    fn load_rel_mut(&mut self, idx: W) -> Option<&mut W>;
+    fn advance(&mut self, amount: usize) -> &mut Self
+    where
+        Self: Sized;
+    fn jump_to(&mut self, to: usize) -> &mut Self
+    where
+        Self: Sized;
     fn exit(&mut self);
+    fn fuel(&self) -> Option<u64>;
+    fn set_fuel(&mut self, fuel: Option<u64>);
+    fn overflow_policy(&self) -> OverflowPolicy;
 }
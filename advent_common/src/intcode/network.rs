@@ -0,0 +1,353 @@
+//! Runs several `VM`s round-robin on one thread, switching node whenever
+//! one outputs or asks for input.
+
+use std::collections::VecDeque;
+use std::mem;
+
+use crate::intcode::{Runable, Status, VMType, Word, VM};
+
+/// How many instructions a node runs before the next node gets a turn.
+const SLICE: u64 = 10_000;
+
+pub enum Topology<W = i64> {
+    /// Each node feeds the next; the last node's output leaves the network.
+    Pipeline,
+    /// A pipeline whose last node feeds the first.
+    Ring,
+    /// Nodes get their index as first input and output a destination
+    /// followed by `payload` words. A node with nothing queued reads
+    /// `empty_input`, and packets for unknown addresses go to the caller.
+    Packets { payload: usize, empty_input: W },
+}
+
+#[derive(Debug)]
+pub enum NodeStatus {
+    Running,
+    WaitingForInput,
+    /// The node's fuel ran out. It keeps its state but won't run again.
+    OutOfFuel,
+    Halted,
+    Failed(anyhow::Error),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome<W = i64> {
+    /// Every node has halted, successfully or not.
+    Halted,
+    /// Every node that hasn't halted is waiting on an empty input queue or
+    /// has run out of fuel.
+    Deadlocked,
+    /// Every packet node has read `empty_input` since it last sent or
+    /// received a packet, and no packets are queued.
+    Idle,
+    /// A packet addressed to a node outside the network, address first.
+    Unrouted(Vec<W>),
+}
+
+struct Node<W> {
+    vm: VM<W>,
+    status: NodeStatus,
+    inbox: VecDeque<W>,
+    outbox: Vec<W>,
+    idle: bool,
+    last_output: Option<W>,
+}
+
+pub struct Network<W = i64> {
+    nodes: Vec<Node<W>>,
+    topology: Topology<W>,
+    output: Vec<W>,
+    unrouted: VecDeque<Vec<W>>,
+}
+
+impl<W: Word> Network<W> {
+    pub fn new(vms: Vec<VM<W>>, topology: Topology<W>) -> Self {
+        let packets = matches!(topology, Topology::Packets { .. });
+        let nodes = vms
+            .into_iter()
+            .enumerate()
+            .map(|(address, vm)| Node {
+                vm,
+                status: NodeStatus::Running,
+                inbox: if packets {
+                    vec![W::from_i64(address as i64)].into()
+                } else {
+                    VecDeque::new()
+                },
+                outbox: Vec::new(),
+                idle: false,
+                last_output: None,
+            })
+            .collect();
+        Self {
+            nodes,
+            topology,
+            output: Vec::new(),
+            unrouted: VecDeque::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Queues `value` as input for `node`.
+    pub fn input(&mut self, node: usize, value: W) -> &mut Self {
+        if let Some(node) = self.nodes.get_mut(node) {
+            node.inbox.push_back(value);
+            node.idle = false;
+        }
+        self
+    }
+
+    /// Queues a packet's payload as input for `node`.
+    pub fn send(&mut self, node: usize, payload: &[W]) -> &mut Self {
+        for value in payload {
+            self.input(node, value.clone());
+        }
+        self
+    }
+
+    pub fn status(&self, node: usize) -> Option<&NodeStatus> {
+        self.nodes.get(node).map(|node| &node.status)
+    }
+
+    pub fn last_output(&self, node: usize) -> Option<&W> {
+        self.nodes
+            .get(node)
+            .and_then(|node| node.last_output.as_ref())
+    }
+
+    pub fn vm(&self, node: usize) -> Option<&VM<W>> {
+        self.nodes.get(node).map(|node| &node.vm)
+    }
+
+    /// Drains the values that have left the end of a pipeline.
+    pub fn take_output(&mut self) -> Vec<W> {
+        mem::take(&mut self.output)
+    }
+
+    pub fn run(&mut self) -> Outcome<W> {
+        loop {
+            let mut progressed = false;
+            for idx in 0..self.nodes.len() {
+                progressed |= self.step_node(idx);
+                if let Some(packet) = self.unrouted.pop_front() {
+                    return Outcome::Unrouted(packet);
+                }
+            }
+            let mut live = self.nodes.iter().filter(|node| {
+                matches!(
+                    node.status,
+                    NodeStatus::Running | NodeStatus::WaitingForInput | NodeStatus::OutOfFuel
+                )
+            });
+            if live.clone().next().is_none() {
+                return Outcome::Halted;
+            }
+            if !progressed {
+                return Outcome::Deadlocked;
+            }
+            if let Topology::Packets { .. } = self.topology {
+                if live.all(|node| {
+                    matches!(node.status, NodeStatus::OutOfFuel)
+                        || (node.idle && node.inbox.is_empty())
+                }) {
+                    return Outcome::Idle;
+                }
+            }
+        }
+    }
+
+    fn step_node(&mut self, idx: usize) -> bool {
+        let node = &mut self.nodes[idx];
+        let status = match node.status {
+            NodeStatus::OutOfFuel | NodeStatus::Halted | NodeStatus::Failed(_) => return false,
+            NodeStatus::Running => node.vm.run_for(SLICE),
+            NodeStatus::WaitingForInput => {
+                let value = match node.inbox.pop_front() {
+                    Some(value) => value,
+                    None => match &self.topology {
+                        Topology::Packets { empty_input, .. } => {
+                            node.idle = true;
+                            empty_input.clone()
+                        }
+                        _ => return false,
+                    },
+                };
+                match node.vm.provide_input(value) {
+                    Ok(()) => node.vm.run_for(SLICE),
+                    Err(e) => Status::Exited(Err(e)),
+                }
+            }
+        };
+        let (next, output) = match status {
+            Status::Exited(Ok(())) => (NodeStatus::Halted, None),
+            Status::Exited(Err(e)) => (NodeStatus::Failed(e), None),
+            Status::RequiresInput => (NodeStatus::WaitingForInput, None),
+            Status::HasOutput(value) => (NodeStatus::Running, Some(value)),
+            Status::Yielded if node.vm.fuel() == Some(0) => {
+                node.status = NodeStatus::OutOfFuel;
+                return false;
+            }
+            Status::Yielded => (NodeStatus::Running, None),
+        };
+        node.status = next;
+        if let Some(value) = output {
+            self.route(idx, value);
+        }
+        true
+    }
+
+    fn route(&mut self, from: usize, value: W) {
+        self.nodes[from].last_output = Some(value.clone());
+        let len = self.nodes.len();
+        match &self.topology {
+            Topology::Pipeline if from + 1 == len => self.output.push(value),
+            Topology::Pipeline => self.nodes[from + 1].inbox.push_back(value),
+            Topology::Ring => self.nodes[(from + 1) % len].inbox.push_back(value),
+            Topology::Packets { payload, .. } => {
+                let payload = *payload;
+                let node = &mut self.nodes[from];
+                node.idle = false;
+                node.outbox.push(value);
+                if node.outbox.len() <= payload {
+                    return;
+                }
+                let packet = mem::take(&mut node.outbox);
+                match packet[0].to_usize().filter(|&address| address < len) {
+                    Some(address) => {
+                        self.send(address, &packet[1..]);
+                    }
+                    None => self.unrouted.push_back(packet),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::testing::load;
+    use crate::intcode::{Program, VMType};
+    use anyhow::Result;
+
+    fn vms(program: &Program, n: usize) -> Result<Vec<VM>> {
+        (0..n).map(|_| load(program)).collect()
+    }
+
+    fn amplifiers(source: &str, phases: &[i64], topology: Topology) -> Result<Network> {
+        let program = Program::from_source(source)?;
+        let mut network = Network::new(vms(&program, phases.len())?, topology);
+        for (node, &phase) in phases.iter().enumerate() {
+            network.input(node, phase);
+        }
+        network.input(0, 0);
+        Ok(network)
+    }
+
+    #[test]
+    fn runs_a_pipeline() -> Result<()> {
+        let mut network = amplifiers(
+            "3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0",
+            &[4, 3, 2, 1, 0],
+            Topology::Pipeline,
+        )?;
+        assert_eq!(network.run(), Outcome::Halted);
+        assert_eq!(network.take_output(), vec![43210]);
+        Ok(())
+    }
+
+    #[test]
+    fn runs_a_ring() -> Result<()> {
+        let mut network = amplifiers(
+            "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5",
+            &[9, 8, 7, 6, 5],
+            Topology::Ring,
+        )?;
+        assert_eq!(network.run(), Outcome::Halted);
+        assert_eq!(network.last_output(4), Some(&139629729));
+        Ok(())
+    }
+
+    #[test]
+    fn reports_deadlock_and_failures() -> Result<()> {
+        let program = Program::from_source("3,0,3,0,99")?;
+        let mut network = Network::new(vms(&program, 2)?, Topology::Ring);
+        network.input(0, 1);
+        assert_eq!(network.run(), Outcome::Deadlocked);
+        assert!(matches!(
+            network.status(0),
+            Some(NodeStatus::WaitingForInput)
+        ));
+
+        let program = Program::from_source("104,1,99")?;
+        let mut vms = vms(&program, 1)?;
+        vms.push(VM::new());
+        vms[1].load_program(&Program::from_source("3,0,42")?)?;
+        let mut network = Network::new(vms, Topology::Pipeline);
+        assert_eq!(network.run(), Outcome::Halted);
+        assert!(matches!(network.status(0), Some(NodeStatus::Halted)));
+        assert!(matches!(network.status(1), Some(NodeStatus::Failed(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn stops_nodes_that_run_out_of_fuel() -> Result<()> {
+        let program = Program::from_source("1105,1,0")?;
+        let mut nodes = vms(&program, 1)?;
+        nodes[0].set_fuel(Some(0));
+        let mut network = Network::new(nodes, Topology::Pipeline);
+        assert_eq!(network.run(), Outcome::Deadlocked);
+        assert!(matches!(network.status(0), Some(NodeStatus::OutOfFuel)));
+
+        let mut nodes = vms(&program, 2)?;
+        nodes[0].set_fuel(Some(3 * SLICE + 1));
+        nodes[1].load_program(&Program::from_source("3,0,99")?)?;
+        let mut network = Network::new(nodes, Topology::Ring);
+        assert_eq!(network.run(), Outcome::Deadlocked);
+        assert!(matches!(network.status(0), Some(NodeStatus::OutOfFuel)));
+        assert_eq!(network.vm(0).and_then(VM::fuel), Some(0));
+        Ok(())
+    }
+
+    #[test]
+    fn routes_packets_until_idle() -> Result<()> {
+        let program = Program::from_assembly(
+            "
+                    in      &address
+                    jt      &address, ireceive
+                    out     i1
+                    out     i7
+            receive: in     &x
+                    eq      &x, i-1, &empty
+                    jt      &empty, ireceive
+                    add     &x, i1, &x
+                    out     i255
+                    out     &x
+                    jt      i1, ireceive
+            address: data   0
+            x:      data    0
+            empty:  data    0
+            ",
+        )?;
+        let mut network = Network::new(
+            vms(&program, 2)?,
+            Topology::Packets {
+                payload: 1,
+                empty_input: -1,
+            },
+        );
+        assert_eq!(network.run(), Outcome::Unrouted(vec![255, 8]));
+        assert_eq!(network.run(), Outcome::Idle);
+        network.send(0, &[41]);
+        assert_eq!(network.run(), Outcome::Unrouted(vec![255, 42]));
+        assert_eq!(network.run(), Outcome::Idle);
+        Ok(())
+    }
+}
@@ -0,0 +1,68 @@
+use std::sync::{Arc, Mutex};
+
+use crate::intcode::opcodes::OpCode;
+
+pub struct Execution<'a, W = i64> {
+    pub ip: usize,
+    pub code: &'a OpCode<W>,
+    pub reads: &'a [W],
+    pub writes: &'a [(usize, W)],
+}
+
+pub trait Observer<W = i64> {
+    fn on_instruction(&mut self, execution: &Execution<W>);
+
+    fn on_input(&mut self, _address: usize, _value: W) {}
+}
+
+pub type SharedObserver<W = i64> = Arc<Mutex<dyn Observer<W> + Send>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{Executor, Program, Runner, VMType, VecPort, VM};
+    use anyhow::Result;
+
+    #[derive(Default)]
+    struct Recorder {
+        lines: Vec<String>,
+    }
+
+    impl Observer for Recorder {
+        fn on_instruction(&mut self, execution: &Execution) {
+            self.lines.push(format!(
+                "{} {:?} {:?}",
+                execution.ip, execution.reads, execution.writes
+            ));
+        }
+
+        fn on_input(&mut self, address: usize, value: i64) {
+            self.lines.push(format!("input {} -> {}", value, address));
+        }
+    }
+
+    #[test]
+    fn reports_reads_and_writes() -> Result<()> {
+        let program = Program::from_source("3,11,1002,11,3,11,109,5,204,6,99,0")?;
+        let recorder = Arc::new(Mutex::new(Recorder::default()));
+        let mut vm = VM::new();
+        vm.load_program(&program)?;
+        vm.attach_observer(recorder.clone());
+        let mut port = VecPort::new();
+        port.input(7);
+        let output: Vec<i64> = Executor::run(vm, port).collect::<Result<_>>()?;
+        assert_eq!(output, vec![21]);
+        assert_eq!(
+            recorder.lock().unwrap().lines,
+            vec![
+                "0 [] []",
+                "input 7 -> 11",
+                "2 [7, 3] [(11, 21)]",
+                "6 [5] []",
+                "8 [21] []",
+                "10 [] []",
+            ]
+        );
+        Ok(())
+    }
+}
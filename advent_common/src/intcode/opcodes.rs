@@ -1,94 +1,201 @@
+use crate::intcode::decoding::{Decoding, PLACES};
 use crate::intcode::errors::{ErrorKinds, OutOfBoundsReference};
-use crate::intcode::parameters::{BinaryParams, ConditionParams, UnaryParams};
-use crate::intcode::VMType;
+use crate::intcode::extensions::{ExtensionParams, Role};
+use crate::intcode::parameters::{BinaryParams, ConditionParams, Parameter, UnaryParams};
+use crate::intcode::{VMType, Word};
 
 use anyhow::Result;
 use std::fmt::{Display, Error, Formatter};
 
-#[derive(Debug, PartialEq, Eq)]
-pub enum OpCode {
-    Add(BinaryParams),
-    Mul(BinaryParams),
-    LessThan(BinaryParams),
-    Equals(BinaryParams),
-    InputInteger(UnaryParams),
-    OutputInteger(UnaryParams),
-    JumpIfTrue(ConditionParams),
-    JumpIfFalse(ConditionParams),
-    SetRelativeBase(UnaryParams),
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum OpCode<W = i64> {
+    Add(BinaryParams<W>),
+    Mul(BinaryParams<W>),
+    LessThan(BinaryParams<W>),
+    Equals(BinaryParams<W>),
+    InputInteger(UnaryParams<W>),
+    OutputInteger(UnaryParams<W>),
+    JumpIfTrue(ConditionParams<W>),
+    JumpIfFalse(ConditionParams<W>),
+    SetRelativeBase(UnaryParams<W>),
     Exit,
+    Extension(ExtensionParams<W>),
 }
 
-static PLACES: [i64; 3] = [10000, 1000, 100];
+fn jump_target<W: Word>(location: W) -> Result<usize> {
+    if location.is_negative() {
+        return Err(ErrorKinds::ReferenceLessThanZeroError.into());
+    }
+    Ok(location.to_usize().ok_or(ErrorKinds::MemoryError(
+        OutOfBoundsReference::ReferenceParameter,
+    ))?)
+}
 
-impl OpCode {
+impl<W: Word> OpCode<W> {
+    #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> usize {
         match self {
             &OpCode::Add(_) | &OpCode::Mul(_) | &OpCode::LessThan(_) | &OpCode::Equals(_) => 4,
             &OpCode::JumpIfTrue(_) | &OpCode::JumpIfFalse(_) => 3,
             &OpCode::InputInteger(_) | &OpCode::OutputInteger(_) | &OpCode::SetRelativeBase(_) => 2,
             &OpCode::Exit => 1,
+            OpCode::Extension(ExtensionParams { parameters, .. }) => 1 + parameters.len(),
         }
     }
 
-    pub fn parse(instructions: &[i64]) -> Result<Self> {
-        let mut parameters: [u8; 3] = [0, 0, 0];
-        if let Some(first) = instructions.first() {
-            let mut value = *first;
-            for (idx, &place) in PLACES.iter().enumerate() {
-                let mut count = 0;
-                while value >= place {
-                    value -= place;
-                    count += 1;
-                }
-                parameters[idx] = count;
+    pub fn parameters(&self) -> Vec<Parameter<W>> {
+        match self {
+            OpCode::Add(BinaryParams { left, right, out })
+            | OpCode::Mul(BinaryParams { left, right, out })
+            | OpCode::LessThan(BinaryParams { left, right, out })
+            | OpCode::Equals(BinaryParams { left, right, out }) => {
+                vec![left.clone(), right.clone(), out.clone()]
             }
-            match value {
-                1 => Ok(OpCode::Add(BinaryParams::new(&parameters, instructions)?)),
-                2 => Ok(OpCode::Mul(BinaryParams::new(&parameters, instructions)?)),
-                3 => Ok(OpCode::InputInteger(UnaryParams::new(
-                    &parameters,
-                    instructions,
-                )?)),
-                4 => Ok(OpCode::OutputInteger(UnaryParams::new(
-                    &parameters,
-                    instructions,
-                )?)),
-                5 => Ok(OpCode::JumpIfTrue(ConditionParams::new(
-                    &parameters,
-                    instructions,
-                )?)),
-                6 => Ok(OpCode::JumpIfFalse(ConditionParams::new(
-                    &parameters,
-                    instructions,
-                )?)),
-                7 => Ok(OpCode::LessThan(BinaryParams::new(
-                    &parameters,
-                    instructions,
-                )?)),
-                8 => Ok(OpCode::Equals(BinaryParams::new(
-                    &parameters,
-                    instructions,
-                )?)),
-                9 => Ok(OpCode::SetRelativeBase(UnaryParams::new(
-                    &parameters,
-                    instructions,
-                )?)),
-                99 => Ok(OpCode::Exit),
-                x => Err(ErrorKinds::UnknownOpcodeError(x).into()),
+            OpCode::InputInteger(UnaryParams { value })
+            | OpCode::OutputInteger(UnaryParams { value })
+            | OpCode::SetRelativeBase(UnaryParams { value }) => vec![value.clone()],
+            OpCode::JumpIfTrue(ConditionParams { test, location })
+            | OpCode::JumpIfFalse(ConditionParams { test, location }) => {
+                vec![test.clone(), location.clone()]
             }
+            OpCode::Exit => vec![],
+            OpCode::Extension(ExtensionParams { parameters, .. }) => parameters.clone(),
+        }
+    }
+
+    pub fn read_parameters(&self) -> Vec<Parameter<W>> {
+        match self {
+            OpCode::Add(BinaryParams { left, right, .. })
+            | OpCode::Mul(BinaryParams { left, right, .. })
+            | OpCode::LessThan(BinaryParams { left, right, .. })
+            | OpCode::Equals(BinaryParams { left, right, .. }) => vec![left.clone(), right.clone()],
+            OpCode::InputInteger(_) | OpCode::Exit => vec![],
+            OpCode::Extension(ExtensionParams {
+                extension,
+                parameters,
+            }) => extension
+                .roles
+                .iter()
+                .zip(parameters.iter())
+                .filter(|(&role, _)| role == Role::Read)
+                .map(|(_, parameter)| parameter.clone())
+                .collect(),
+            _ => self.parameters(),
+        }
+    }
+
+    pub fn write_parameter(&self) -> Option<Parameter<W>> {
+        match self {
+            OpCode::Add(BinaryParams { out, .. })
+            | OpCode::Mul(BinaryParams { out, .. })
+            | OpCode::LessThan(BinaryParams { out, .. })
+            | OpCode::Equals(BinaryParams { out, .. }) => Some(out.clone()),
+            OpCode::InputInteger(UnaryParams { value }) => Some(value.clone()),
+            OpCode::Extension(ExtensionParams {
+                extension,
+                parameters,
+            }) => extension
+                .roles
+                .iter()
+                .zip(parameters.iter())
+                .find(|(&role, _)| role == Role::Write)
+                .map(|(_, parameter)| parameter.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn opcode(&self) -> i64 {
+        match self {
+            OpCode::Add(_) => 1,
+            OpCode::Mul(_) => 2,
+            OpCode::InputInteger(_) => 3,
+            OpCode::OutputInteger(_) => 4,
+            OpCode::JumpIfTrue(_) => 5,
+            OpCode::JumpIfFalse(_) => 6,
+            OpCode::LessThan(_) => 7,
+            OpCode::Equals(_) => 8,
+            OpCode::SetRelativeBase(_) => 9,
+            OpCode::Exit => 99,
+            OpCode::Extension(ExtensionParams { extension, .. }) => extension.opcode,
+        }
+    }
+
+    /// The words `parse` decodes back into this instruction.
+    pub fn encode(&self) -> Vec<W> {
+        let parameters = self.parameters();
+        let mut instruction = self.opcode();
+        for (parameter, place) in parameters.iter().zip(PLACES.iter().rev()) {
+            instruction += parameter.mode() * place;
+        }
+        let mut words = vec![W::from_i64(instruction)];
+        words.extend(parameters.iter().map(Parameter::word));
+        words
+    }
+
+    /// Decodes the instruction at the start of `instructions` strictly.
+    pub fn parse(instructions: &[W]) -> Result<Self> {
+        Self::decode(instructions, Decoding::Strict)
+    }
+
+    pub fn decode(instructions: &[W], decoding: Decoding) -> Result<Self> {
+        if let Some(first) = instructions.first() {
+            let value = first
+                .to_i64()
+                .ok_or_else(|| ErrorKinds::UnknownOpcodeError(first.to_string()))?;
+            let (opcode, modes) = decoding.split(value)?;
+            Self::with_modes(opcode, &modes, instructions)
         } else {
             Err(ErrorKinds::MemoryError(OutOfBoundsReference::OpCodeLength).into())
         }
     }
 
-    pub fn exec<V: VMType>(self, vm: &mut V) -> Result<bool> {
+    /// Builds a puzzle instruction from an opcode that's already been split
+    /// from its modes, which are ordered the way `Decoding::split` returns
+    /// them.
+    pub(crate) fn with_modes(opcode: i64, modes: &[u8; 3], instructions: &[W]) -> Result<Self> {
+        match opcode {
+            1 => Ok(OpCode::Add(BinaryParams::new(modes, instructions)?)),
+            2 => Ok(OpCode::Mul(BinaryParams::new(modes, instructions)?)),
+            3 => Ok(OpCode::InputInteger(UnaryParams::new(modes, instructions)?)),
+            4 => Ok(OpCode::OutputInteger(UnaryParams::new(
+                modes,
+                instructions,
+            )?)),
+            5 => Ok(OpCode::JumpIfTrue(ConditionParams::new(
+                modes,
+                instructions,
+            )?)),
+            6 => Ok(OpCode::JumpIfFalse(ConditionParams::new(
+                modes,
+                instructions,
+            )?)),
+            7 => Ok(OpCode::LessThan(BinaryParams::new(modes, instructions)?)),
+            8 => Ok(OpCode::Equals(BinaryParams::new(modes, instructions)?)),
+            9 => Ok(OpCode::SetRelativeBase(UnaryParams::new(
+                modes,
+                instructions,
+            )?)),
+            99 => Ok(OpCode::Exit),
+            x => Err(ErrorKinds::UnknownOpcodeError(x.to_string()).into()),
+        }
+    }
+
+    pub fn exec<V: VMType<W>>(self, vm: &mut V) -> Result<bool> {
+        let len = self.len();
         match self {
             OpCode::Add(BinaryParams { left, right, out }) => {
-                *out.read_mut(vm)? = left.read(vm)? + right.read(vm)?;
+                let value = vm
+                    .overflow_policy()
+                    .add(&left.read(vm)?, &right.read(vm)?)
+                    .ok_or(ErrorKinds::ArithmeticOverflowError)?;
+                *out.read_mut(vm)? = value;
             }
             OpCode::Mul(BinaryParams { left, right, out }) => {
-                *out.read_mut(vm)? = left.read(vm)? * right.read(vm)?;
+                let value = vm
+                    .overflow_policy()
+                    .mul(&left.read(vm)?, &right.read(vm)?)
+                    .ok_or(ErrorKinds::ArithmeticOverflowError)?;
+                *out.read_mut(vm)? = value;
             }
             OpCode::InputInteger(UnaryParams { value }) => {
                 vm.input_to(value);
@@ -98,44 +205,48 @@ impl OpCode {
             }
             OpCode::LessThan(BinaryParams { left, right, out }) => {
                 *out.read_mut(vm)? = if left.read(vm)? < right.read(vm)? {
-                    1
+                    W::from_i64(1)
                 } else {
-                    0
+                    W::from_i64(0)
                 };
             }
             OpCode::Equals(BinaryParams { left, right, out }) => {
                 *out.read_mut(vm)? = if left.read(vm)? == right.read(vm)? {
-                    1
+                    W::from_i64(1)
                 } else {
-                    0
+                    W::from_i64(0)
                 };
             }
             OpCode::JumpIfTrue(ConditionParams { test, location }) => {
-                if test.read(vm)? != 0 {
-                    vm.jump_to(location.read(vm)? as usize);
+                if !test.read(vm)?.is_zero() {
+                    vm.jump_to(jump_target(location.read(vm)?)?);
                     return Ok(false);
                 }
             }
             OpCode::JumpIfFalse(ConditionParams { test, location }) => {
-                if test.read(vm)? == 0 {
-                    vm.jump_to(location.read(vm)? as usize);
+                if test.read(vm)?.is_zero() {
+                    vm.jump_to(jump_target(location.read(vm)?)?);
                     return Ok(false);
                 }
             }
             OpCode::SetRelativeBase(UnaryParams { value }) => {
-                vm.offset_relative_base(value.read(vm)?)
+                vm.offset_relative_base(value.read(vm)?)?
             }
             OpCode::Exit => {
                 vm.exit();
                 return Ok(true);
             }
+            OpCode::Extension(ExtensionParams {
+                extension,
+                parameters,
+            }) => extension.exec(vm, &parameters)?,
         };
-        vm.advance(self.len());
+        vm.advance(len);
         Ok(false)
     }
 }
 
-impl Display for OpCode {
+impl<W: Display> Display for OpCode<W> {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
         match self {
             OpCode::Add(p) => write!(f, "add\t\t{}.", p),
@@ -148,6 +259,7 @@ impl Display for OpCode {
             OpCode::JumpIfFalse(p) => write!(f, "jf\t\t{}.", p),
             OpCode::SetRelativeBase(p) => write!(f, "srb\t\t{}.", p),
             OpCode::Exit => write!(f, "exit."),
+            OpCode::Extension(p) => write!(f, "{}\t\t{}.", p.extension.name, p),
         }
     }
 }
@@ -0,0 +1,284 @@
+//! A peephole optimizer that folds constant arithmetic and threads jumps.
+//! Rewrites keep their length, and only touch instructions whose words
+//! nothing else reads, writes or decodes. Programs with relative mode
+//! parameters, computed jumps or writes into code are left alone.
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::intcode::analysis::Analysis;
+use crate::intcode::disassembler::Disassembly;
+use crate::intcode::opcodes::OpCode;
+use crate::intcode::parameters::{BinaryParams, ConditionParams, Parameter};
+use crate::intcode::{OverflowPolicy, Program, Word};
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Optimized<W = i64> {
+    pub program: Program<W>,
+    /// Reachable instructions before optimizing.
+    pub instructions_before: usize,
+    /// Reachable instructions after optimizing.
+    pub instructions_after: usize,
+    pub rewrites: usize,
+}
+
+fn store<W: Word>(value: W, out: &Parameter<W>) -> OpCode<W> {
+    OpCode::Add(BinaryParams {
+        left: Parameter::Immediate(value),
+        right: Parameter::Immediate(W::default()),
+        out: out.clone(),
+    })
+}
+
+fn fold<W: Word>(code: &OpCode<W>) -> Option<OpCode<W>> {
+    let (params, fold): (_, fn(&W, &W) -> Option<W>) = match code {
+        OpCode::Add(params) => (params, |l, r| OverflowPolicy::Checked.add(l, r)),
+        OpCode::Mul(params) => (params, |l, r| OverflowPolicy::Checked.mul(l, r)),
+        OpCode::LessThan(params) => (params, |l, r| Some(W::from_i64((l < r) as i64))),
+        OpCode::Equals(params) => (params, |l, r| Some(W::from_i64((l == r) as i64))),
+        _ => return None,
+    };
+    let value = match (&params.left, &params.right) {
+        (Parameter::Immediate(left), Parameter::Immediate(right)) => fold(left, right)?,
+        (Parameter::Immediate(zero), _) | (_, Parameter::Immediate(zero))
+            if matches!(code, OpCode::Mul(_)) && zero.is_zero() =>
+        {
+            W::default()
+        }
+        _ => return None,
+    };
+    let folded = store(value, &params.out);
+    if folded == *code {
+        None
+    } else {
+        Some(folded)
+    }
+}
+
+/// Where an unconditional jump goes, if `code` is one.
+fn unconditional_target<W: Word>(code: &OpCode<W>) -> Option<usize> {
+    match code {
+        OpCode::JumpIfTrue(ConditionParams {
+            test: Parameter::Immediate(test),
+            location: Parameter::Immediate(target),
+        }) if !test.is_zero() => target.to_usize(),
+        OpCode::JumpIfFalse(ConditionParams {
+            test: Parameter::Immediate(test),
+            location: Parameter::Immediate(target),
+        }) if test.is_zero() => target.to_usize(),
+        _ => None,
+    }
+}
+
+struct Optimizer<'a, W> {
+    disassembly: Disassembly<'a, W>,
+    movable: BTreeSet<usize>,
+}
+
+impl<'a, W: Word> Optimizer<'a, W> {
+    fn new(words: &'a [W], analysis: &Analysis<W>) -> Option<Self> {
+        let disassembly = Disassembly::new(words);
+        if analysis.has_computed_jumps() || !analysis.self_modifying.is_empty() {
+            return None;
+        }
+        let mut accessed = BTreeSet::new();
+        let mut claims: HashMap<usize, usize> = HashMap::new();
+        for (address, code) in disassembly.instructions() {
+            for parameter in code.parameters() {
+                match parameter {
+                    Parameter::Relative(_) => return None,
+                    Parameter::Reference(cell) => {
+                        accessed.insert(cell);
+                    }
+                    Parameter::Immediate(_) => {}
+                }
+            }
+            for word in address..address + code.len() {
+                *claims.entry(word).or_insert(0) += 1;
+            }
+        }
+        let movable = disassembly
+            .instructions()
+            .filter(|(address, code)| {
+                (*address..address + code.len())
+                    .all(|word| claims.get(&word) == Some(&1) && !accessed.contains(&word))
+            })
+            .map(|(address, _)| address)
+            .collect();
+        Some(Self {
+            disassembly,
+            movable,
+        })
+    }
+
+    /// Follows a chain of unconditional jumps that start at `target`.
+    fn thread(&self, mut target: usize) -> usize {
+        let mut seen = BTreeSet::new();
+        while self.movable.contains(&target) && seen.insert(target) {
+            match self
+                .disassembly
+                .instruction(target)
+                .and_then(unconditional_target)
+            {
+                Some(next) => target = next,
+                None => break,
+            }
+        }
+        target
+    }
+
+    fn rewrite(&self, code: &OpCode<W>) -> Option<OpCode<W>> {
+        if let Some(folded) = fold(code) {
+            return Some(folded);
+        }
+        let (params, jump_if_true) = match code {
+            OpCode::JumpIfTrue(params) => (params, true),
+            OpCode::JumpIfFalse(params) => (params, false),
+            _ => return None,
+        };
+        let target = match &params.location {
+            Parameter::Immediate(target) => target.to_usize()?,
+            _ => return None,
+        };
+        let threaded = self.thread(target);
+        if threaded == target {
+            return None;
+        }
+        let params = ConditionParams {
+            test: params.test.clone(),
+            location: Parameter::Immediate(W::from_i64(threaded as i64)),
+        };
+        Some(if jump_if_true {
+            OpCode::JumpIfTrue(params)
+        } else {
+            OpCode::JumpIfFalse(params)
+        })
+    }
+}
+
+impl<W: Word> Program<W> {
+    pub fn optimize(&self) -> Optimized<W> {
+        let analysis = self.analyze();
+        let mut words = self.load();
+        let mut rewrites = 0;
+        if let Some(optimizer) = Optimizer::new(self.as_inner(), &analysis) {
+            for &address in optimizer.movable.iter() {
+                let code = match optimizer.disassembly.instruction(address) {
+                    Some(code) => code,
+                    None => continue,
+                };
+                if let Some(rewritten) = optimizer.rewrite(code) {
+                    let encoded = rewritten.encode();
+                    debug_assert_eq!(encoded.len(), code.len());
+                    words[address..address + encoded.len()].clone_from_slice(&encoded);
+                    rewrites += 1;
+                }
+            }
+        }
+        let program = Program::from(words);
+        let instructions_after = program.analyze().reachable.len();
+        Optimized {
+            program,
+            instructions_before: analysis.reachable.len(),
+            instructions_after,
+            rewrites,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::testing::{load, port};
+    use crate::intcode::{Executor, Runable, Runner, Status, VMType};
+    use anyhow::Result;
+
+    fn run(program: &Program, inputs: &[i64]) -> Result<(Vec<i64>, u64)> {
+        let mut vm = load(program)?;
+        vm.set_fuel(Some(u64::MAX));
+        let mut executor = Executor::run(vm, port(inputs));
+        let output = (&mut executor).collect::<Result<_>>()?;
+        Ok((output, u64::MAX - executor.vm().fuel().unwrap_or(0)))
+    }
+
+    #[test]
+    fn folds_constants_and_threads_jumps() -> Result<()> {
+        let program = Program::from_assembly(
+            "
+                    in      &n
+                    mul     i6, i7, &answer
+                    mul     &n, i0, &zero
+                    jt      &n, ihop
+                    out     &zero
+            hop:    jf      i0, ihop2
+            hop2:   jt      i1, iend
+                    out     i-1
+            end:    out     &answer
+                    exit
+            n:      data    0
+            answer: data    0
+            zero:   data    0
+            ",
+        )?;
+        let optimized = program.optimize();
+        assert_eq!(optimized.rewrites, 4);
+        assert_eq!(optimized.instructions_before, 9);
+        assert_eq!(optimized.instructions_after, 8);
+        for input in &[0, 1] {
+            let (before, steps_before) = run(&program, &[*input])?;
+            let (after, steps_after) = run(&optimized.program, &[*input])?;
+            assert_eq!(before, after);
+            assert!(steps_after < steps_before);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn leaves_self_modifying_and_relative_code_alone() -> Result<()> {
+        let programs = [
+            "1101,2,3,1,1105,1,7,1105,1,9,99",
+            "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99",
+            "1104,1,99",
+        ];
+        for source in programs.iter() {
+            let program = Program::from_source(source)?;
+            let optimized = program.optimize();
+            assert_eq!(optimized.rewrites, 0, "{} should be left alone", source);
+            assert_eq!(optimized.program, program);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_the_day_9_examples_working() -> Result<()> {
+        let examples: [(&str, &[i64]); 3] = [
+            (
+                "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99",
+                &[
+                    109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+                ],
+            ),
+            ("1102,34915192,34915192,7,4,7,99,0", &[1219070632396864]),
+            ("104,1125899906842624,99", &[1125899906842624]),
+        ];
+        for (source, expected) in examples.iter() {
+            let program = Program::from_source(source)?;
+            let optimized = program.optimize();
+            assert_eq!(run(&optimized.program, &[])?.0, *expected);
+            let mut vm = load(&optimized.program)?;
+            assert!(!matches!(vm.run(), Status::Exited(Err(_))));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn encodes_what_parse_decodes() -> Result<()> {
+        let words = Program::from_source("21101,1,4,-3,1202,2,-7,0,99")?.load();
+        let mut address = 0;
+        while address < words.len() {
+            let code = OpCode::parse(&words[address..])?;
+            assert_eq!(code.encode(), &words[address..address + code.len()]);
+            address += code.len();
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,81 @@
+use crate::intcode::Word;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum OverflowPolicy {
+    #[default]
+    Checked,
+    Wrapping,
+    Saturating,
+}
+
+impl OverflowPolicy {
+    pub fn add<W: Word>(self, left: &W, right: &W) -> Option<W> {
+        match self {
+            OverflowPolicy::Checked => left.checked_add(right),
+            OverflowPolicy::Wrapping => Some(left.wrapping_add(right)),
+            OverflowPolicy::Saturating => Some(left.saturating_add(right)),
+        }
+    }
+
+    pub fn mul<W: Word>(self, left: &W, right: &W) -> Option<W> {
+        match self {
+            OverflowPolicy::Checked => left.checked_mul(right),
+            OverflowPolicy::Wrapping => Some(left.wrapping_mul(right)),
+            OverflowPolicy::Saturating => Some(left.saturating_mul(right)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::testing;
+    use anyhow::Result;
+
+    fn run(source: &str, policy: OverflowPolicy) -> Result<Vec<i64>> {
+        let mut vm = testing::vm(source)?;
+        vm.set_overflow_policy(policy);
+        testing::run(vm, &[])
+    }
+
+    static ADD: &str = "1101,9223372036854775807,1,9,4,9,99,0,0,0";
+    static MUL: &str = "1102,-9223372036854775807,2,9,4,9,99,0,0,0";
+
+    #[test]
+    fn checked_arithmetic_reports_the_ip() {
+        let err = run(
+            "104,1,1101,9223372036854775807,1,0,99",
+            OverflowPolicy::Checked,
+        )
+        .expect_err("the add should overflow");
+        assert_eq!(err.to_string(), "arithmetic overflow at ip 2");
+        assert!(run(MUL, OverflowPolicy::Checked).is_err());
+    }
+
+    #[test]
+    fn wrapping_arithmetic() -> Result<()> {
+        assert_eq!(run(ADD, OverflowPolicy::Wrapping)?, vec![i64::MIN]);
+        assert_eq!(run(MUL, OverflowPolicy::Wrapping)?, vec![2]);
+        Ok(())
+    }
+
+    #[test]
+    fn saturating_arithmetic() -> Result<()> {
+        assert_eq!(run(ADD, OverflowPolicy::Saturating)?, vec![i64::MAX]);
+        assert_eq!(run(MUL, OverflowPolicy::Saturating)?, vec![i64::MIN]);
+        Ok(())
+    }
+
+    #[test]
+    fn relative_base_arithmetic() -> Result<()> {
+        let source = "109,9223372036854775807,109,1,204,-9223372036854775807,99";
+        assert!(run(source, OverflowPolicy::Checked).is_err());
+        assert_eq!(
+            run(source, OverflowPolicy::Wrapping)?,
+            vec![i64::MAX],
+            "the base wraps around to address 1"
+        );
+        assert_eq!(run(source, OverflowPolicy::Saturating)?, vec![109]);
+        Ok(())
+    }
+}
@@ -1,67 +1,88 @@
 use anyhow::Result;
 
 use crate::intcode::errors::{ErrorKinds, OutOfBoundsReference};
-use crate::intcode::VMType;
+use crate::intcode::{VMType, Word};
 use std::fmt::{Display, Error, Formatter};
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
-pub enum Parameter {
-    Immediate(i64),
+pub enum Parameter<W = i64> {
+    Immediate(W),
     Reference(usize),
-    Relative(i64),
+    Relative(W),
 }
 
-impl Parameter {
-    pub fn new(idx: usize, mode: u8, instructions: &[i64]) -> Result<Self> {
+impl<W: Word> Parameter<W> {
+    pub fn new(idx: usize, mode: u8, instructions: &[W]) -> Result<Self> {
+        let intcode = instructions
+            .get(idx)
+            .ok_or(ErrorKinds::MemoryError(OutOfBoundsReference::OpCodeLength))?
+            .clone();
         Ok(if mode == 1 {
-            Parameter::Immediate(
-                *instructions
-                    .get(idx)
-                    .ok_or(ErrorKinds::MemoryError(OutOfBoundsReference::OpCodeLength))?,
-            )
+            Parameter::Immediate(intcode)
         } else if mode == 0 {
-            let intcode = *instructions
-                .get(idx)
-                .ok_or(ErrorKinds::MemoryError(OutOfBoundsReference::OpCodeLength))?;
-            if intcode < 0 {
+            if intcode.is_negative() {
                 return Err(ErrorKinds::ReferenceLessThanZeroError.into());
             }
-            Parameter::Reference(intcode as usize)
+            Parameter::Reference(intcode.to_usize().ok_or(ErrorKinds::MemoryError(
+                OutOfBoundsReference::ReferenceParameter,
+            ))?)
         } else {
-            Parameter::Relative(
-                *instructions
-                    .get(idx)
-                    .ok_or(ErrorKinds::MemoryError(OutOfBoundsReference::OpCodeLength))?,
-            )
+            Parameter::Relative(intcode)
         })
     }
 
-    pub fn read<V: VMType>(self, vm: &V) -> Result<i64> {
+    /// The mode digit this parameter is encoded with.
+    pub fn mode(&self) -> i64 {
+        match self {
+            Parameter::Reference(_) => 0,
+            Parameter::Immediate(_) => 1,
+            Parameter::Relative(_) => 2,
+        }
+    }
+
+    /// The word this parameter is encoded as.
+    pub fn word(&self) -> W {
+        match self {
+            Parameter::Immediate(value) | Parameter::Relative(value) => value.clone(),
+            Parameter::Reference(address) => W::from_i64(*address as i64),
+        }
+    }
+
+    pub fn read<V: VMType<W> + ?Sized>(self, vm: &V) -> Result<W> {
         Ok(match self {
             Parameter::Immediate(x) => x,
-            Parameter::Reference(r) => *vm.load(r as usize).ok_or(ErrorKinds::MemoryError(
-                OutOfBoundsReference::ReferenceParameter,
-            ))?,
-            Parameter::Relative(r) => *vm.load_rel(r).ok_or(ErrorKinds::MemoryError(
-                OutOfBoundsReference::RelativeParameter,
-            ))?,
+            Parameter::Reference(r) => vm
+                .load(r)
+                .ok_or(ErrorKinds::MemoryError(
+                    OutOfBoundsReference::ReferenceParameter,
+                ))?
+                .clone(),
+            Parameter::Relative(r) => vm
+                .load(vm.relative_address(r)?)
+                .ok_or(ErrorKinds::MemoryError(
+                    OutOfBoundsReference::RelativeParameter,
+                ))?
+                .clone(),
         })
     }
 
-    pub fn read_mut<V: VMType>(self, vm: &mut V) -> Result<&mut i64> {
+    pub fn read_mut<V: VMType<W> + ?Sized>(self, vm: &mut V) -> Result<&mut W> {
         match self {
             Parameter::Reference(r) => Ok(vm.load_mut(r).ok_or(ErrorKinds::MemoryError(
                 OutOfBoundsReference::ReferenceParameter,
             ))?),
-            Parameter::Relative(r) => Ok(vm.load_rel_mut(r).ok_or(ErrorKinds::MemoryError(
-                OutOfBoundsReference::ReferenceParameter,
-            ))?),
+            Parameter::Relative(r) => {
+                let address = vm.relative_address(r)?;
+                Ok(vm.load_mut(address).ok_or(ErrorKinds::MemoryError(
+                    OutOfBoundsReference::RelativeParameter,
+                ))?)
+            }
             Parameter::Immediate(_) => Err(ErrorKinds::ImmediateModeOutputError.into()),
         }
     }
 }
 
-impl Display for Parameter {
+impl<W: Display> Display for Parameter<W> {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
         match self {
             Parameter::Immediate(val) => write!(f, "i{:04}", val),
@@ -71,15 +92,15 @@ impl Display for Parameter {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub struct BinaryParams {
-    pub left: Parameter,
-    pub right: Parameter,
-    pub out: Parameter,
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct BinaryParams<W = i64> {
+    pub left: Parameter<W>,
+    pub right: Parameter<W>,
+    pub out: Parameter<W>,
 }
 
-impl BinaryParams {
-    pub fn new(parameters: &[u8; 3], instructions: &[i64]) -> Result<Self> {
+impl<W: Word> BinaryParams<W> {
+    pub fn new(parameters: &[u8; 3], instructions: &[W]) -> Result<Self> {
         Ok(Self {
             left: Parameter::new(1, parameters[2], instructions)?,
             right: Parameter::new(2, parameters[1], instructions)?,
@@ -88,7 +109,7 @@ impl BinaryParams {
     }
 }
 
-impl Display for BinaryParams {
+impl<W: Display> Display for BinaryParams<W> {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
         write!(
             f,
@@ -98,33 +119,33 @@ impl Display for BinaryParams {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub struct UnaryParams {
-    pub value: Parameter,
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct UnaryParams<W = i64> {
+    pub value: Parameter<W>,
 }
 
-impl UnaryParams {
-    pub fn new(parameters: &[u8; 3], instructions: &[i64]) -> Result<Self> {
+impl<W: Word> UnaryParams<W> {
+    pub fn new(parameters: &[u8; 3], instructions: &[W]) -> Result<Self> {
         Ok(Self {
             value: Parameter::new(1, parameters[2], instructions)?,
         })
     }
 }
 
-impl Display for UnaryParams {
+impl<W: Display> Display for UnaryParams<W> {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
         write!(f, "[ val:\t{} ]", self.value)
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub struct ConditionParams {
-    pub test: Parameter,
-    pub location: Parameter,
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ConditionParams<W = i64> {
+    pub test: Parameter<W>,
+    pub location: Parameter<W>,
 }
 
-impl ConditionParams {
-    pub fn new(parameters: &[u8; 3], instructions: &[i64]) -> Result<Self> {
+impl<W: Word> ConditionParams<W> {
+    pub fn new(parameters: &[u8; 3], instructions: &[W]) -> Result<Self> {
         Ok(Self {
             test: Parameter::new(1, parameters[2], instructions)?,
             location: Parameter::new(2, parameters[1], instructions)?,
@@ -132,7 +153,7 @@ impl ConditionParams {
     }
 }
 
-impl Display for ConditionParams {
+impl<W: Display> Display for ConditionParams<W> {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
         write!(f, "[ test:\t{}, jump_to:\t{} ]", self.test, self.location)
     }
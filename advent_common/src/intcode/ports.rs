@@ -1,74 +1,197 @@
+use std::collections::VecDeque;
 use std::io::{BufRead, BufReader, Stdin, Stdout, Write};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
 
 use anyhow::{Error, Result};
 
 use crate::intcode::errors::{ErrorKinds, IOError};
-use crate::intcode::{ReadInt, WriteInt};
+use crate::intcode::{Executor, ReadInt, Runner, VMType, Word, WriteInt};
 
+/// A port on stdin and stdout that prompts when a person is typing at it
+/// and reads and writes bare integers when stdin is piped.
 pub fn stdport() -> Port<BufReader<Stdin>, Stdout> {
-    Port::new(BufReader::new(std::io::stdin()), std::io::stdout())
+    let format = if atty::is(atty::Stream::Stdin) {
+        Format::Interactive
+    } else {
+        Format::Raw
+    };
+    Port::with_format(BufReader::new(std::io::stdin()), std::io::stdout(), format)
+}
+
+/// How a `Port` presents its input and output.
+///
+/// Every format but `Interactive` reads any number of integers per line,
+/// separated by commas or whitespace.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Format {
+    /// Prompts for each input and labels each output.
+    Interactive,
+    /// One integer per line.
+    Raw,
+    /// A single comma separated line.
+    Csv,
+    /// One JSON object per output, `{"seq":0,"value":42}`.
+    JsonLines,
 }
 
 pub struct Port<I: BufRead, O: Write> {
     buffer: String,
+    pending: VecDeque<String>,
     input: I,
     output: O,
+    format: Format,
+    written: usize,
 }
 
-impl<I: BufRead, O: Write> WriteInt for Port<I, O> {
-    fn write_int(&mut self, i: i64) -> Result<()> {
-        writeln!(self.output, "output >>> {}", i)
+impl<I: BufRead, O: Write, W: Word> WriteInt<W> for Port<I, O> {
+    fn write_int(&mut self, i: W) -> Result<()> {
+        let result = match self.format {
+            Format::Interactive => writeln!(self.output, "output >>> {}", i),
+            Format::Raw => writeln!(self.output, "{}", i),
+            Format::Csv if self.written == 0 => write!(self.output, "{}", i),
+            Format::Csv => write!(self.output, ",{}", i),
+            Format::JsonLines => {
+                writeln!(self.output, "{{\"seq\":{},\"value\":{}}}", self.written, i)
+            }
+        };
+        self.written += 1;
+        result
+            .and_then(|_| self.output.flush())
             .map_err(|e| ErrorKinds::IOError(IOError::OutputError(e)).into())
     }
 }
 
-impl<I: BufRead, O: Write> ReadInt for Port<I, O> {
-    fn read_int(&mut self) -> Result<i64> {
-        self.buffer.clear();
-        self.output
-            .write_all(b"please enter an int <<< ")
-            .map_err(|e| ErrorKinds::IOError(IOError::OutputError(e)))?;
-        self.output
-            .flush()
-            .map_err(|e| ErrorKinds::IOError(IOError::OutputError(e)))?;
-        self.input
-            .read_line(&mut self.buffer)
-            .map_err(|e| ErrorKinds::IOError(IOError::InputError(e)))?;
-        self.buffer
-            .trim()
+impl<I: BufRead, O: Write, W: Word> ReadInt<W> for Port<I, O> {
+    fn read_int(&mut self) -> Result<W> {
+        while self.pending.is_empty() {
+            self.buffer.clear();
+            if let Format::Interactive = self.format {
+                self.output
+                    .write_all(b"please enter an int <<< ")
+                    .map_err(|e| ErrorKinds::IOError(IOError::OutputError(e)))?;
+                self.output
+                    .flush()
+                    .map_err(|e| ErrorKinds::IOError(IOError::OutputError(e)))?;
+                self.input
+                    .read_line(&mut self.buffer)
+                    .map_err(|e| ErrorKinds::IOError(IOError::InputError(e)))?;
+                self.pending.push_back(self.buffer.trim().to_owned());
+            } else {
+                let read = self
+                    .input
+                    .read_line(&mut self.buffer)
+                    .map_err(|e| ErrorKinds::IOError(IOError::InputError(e)))?;
+                if read == 0 {
+                    return Err(ErrorKinds::IOError(IOError::OutOfStaticInputError).into());
+                }
+                self.pending.extend(
+                    self.buffer
+                        .split(|c: char| c == ',' || c.is_whitespace())
+                        .filter(|token| !token.is_empty())
+                        .map(str::to_owned),
+                );
+            }
+        }
+        let token = self.pending.pop_front().unwrap_or_default();
+        token
             .parse()
-            .map_err(|_| ErrorKinds::IOError(IOError::StringParseError(self.buffer.clone())).into())
+            .map_err(|_| ErrorKinds::IOError(IOError::StringParseError(token)).into())
     }
 }
 
 impl<I: BufRead, O: Write> Port<I, O> {
     pub fn new(input: I, output: O) -> Self {
+        Self::with_format(input, output, Format::Interactive)
+    }
+
+    pub fn with_format(input: I, output: O, format: Format) -> Self {
         Self {
             buffer: String::new(),
+            pending: VecDeque::new(),
             input,
             output,
+            format,
+            written: 0,
+        }
+    }
+}
+
+impl<I: BufRead, O: Write> Drop for Port<I, O> {
+    fn drop(&mut self) {
+        // Finish the line a CSV port has been writing.
+        if self.format == Format::Csv && self.written > 0 {
+            let _ = writeln!(self.output);
+            let _ = self.output.flush();
+        }
+    }
+}
+
+/// What a port does when asked for input it doesn't have.
+pub enum EmptyInput<W = i64> {
+    /// Fail with an error.
+    Error,
+    /// Wait for more input. Only ports fed from elsewhere, like
+    /// `ChannelPort`, can wait; the rest treat this as `Error`.
+    Block,
+    /// Supply a fixed value, like the -1 networked programs expect.
+    Value(W),
+    /// Ask the callback for a value.
+    Callback(Box<dyn FnMut() -> Result<W> + Send>),
+    /// Hand control back to the caller. An `Executor` yields an error that
+    /// `is_input_pending` recognises and resumes once more input arrives.
+    Yield,
+}
+
+impl<W: Clone> EmptyInput<W> {
+    fn on_empty(&mut self) -> Result<W> {
+        match self {
+            EmptyInput::Error | EmptyInput::Block => {
+                Err(ErrorKinds::IOError(IOError::OutOfStaticInputError).into())
+            }
+            EmptyInput::Value(value) => Ok(value.clone()),
+            EmptyInput::Callback(callback) => callback(),
+            EmptyInput::Yield => Err(ErrorKinds::IOError(IOError::InputPendingError).into()),
         }
     }
 }
 
-#[derive(Default)]
-pub struct VecPort {
-    input: Vec<i64>,
-    output: Vec<i64>,
+/// Whether `error` is a port yielding because it has no input yet.
+pub fn is_input_pending(error: &Error) -> bool {
+    matches!(
+        error.downcast_ref::<ErrorKinds>(),
+        Some(ErrorKinds::IOError(IOError::InputPendingError))
+    )
+}
+
+pub struct VecPort<W = i64> {
+    input: Vec<W>,
+    output: Vec<W>,
+    empty_input: EmptyInput<W>,
 }
 
-impl ReadInt for VecPort {
-    fn read_int(&mut self) -> Result<i64> {
+impl<W> Default for VecPort<W> {
+    fn default() -> Self {
+        Self {
+            input: Vec::new(),
+            output: Vec::new(),
+            empty_input: EmptyInput::Error,
+        }
+    }
+}
+
+impl<W: Clone> ReadInt<W> for VecPort<W> {
+    fn read_int(&mut self) -> Result<W> {
         if !self.input.is_empty() {
             Ok(self.input.remove(0))
         } else {
-            Err(ErrorKinds::IOError(IOError::OutOfStaticInputError).into())
+            self.empty_input.on_empty()
         }
     }
 }
 
-impl WriteInt for VecPort {
-    fn write_int(&mut self, i: i64) -> Result<(), Error> {
+impl<W> WriteInt<W> for VecPort<W> {
+    fn write_int(&mut self, i: W) -> Result<(), Error> {
         self.output.push(i);
         Ok(())
     }
@@ -76,22 +199,244 @@ impl WriteInt for VecPort {
 
 impl VecPort {
     pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl<W> VecPort<W> {
+    pub fn with_empty_input(empty_input: EmptyInput<W>) -> Self {
         Self {
-            input: Vec::new(),
-            output: Vec::new(),
+            empty_input,
+            ..Default::default()
         }
     }
 
-    pub fn input(&mut self, i: i64) -> &mut Self {
+    pub fn set_empty_input(&mut self, empty_input: EmptyInput<W>) -> &mut Self {
+        self.empty_input = empty_input;
+        self
+    }
+
+    pub fn input(&mut self, i: W) -> &mut Self {
         self.input.push(i);
         self
     }
 
-    pub fn into_output(self) -> Vec<i64> {
+    pub fn into_output(self) -> Vec<W> {
         self.output
     }
 
-    pub fn output(&self) -> impl Iterator<Item = &i64> {
+    pub fn output(&self) -> impl Iterator<Item = &W> {
         self.output.iter()
     }
 }
+
+pub struct ChannelPort<W = i64> {
+    input: Receiver<W>,
+    output: Sender<W>,
+    empty_input: EmptyInput<W>,
+}
+
+impl<W> ChannelPort<W> {
+    pub fn new(input: Receiver<W>, output: Sender<W>) -> Self {
+        Self {
+            input,
+            output,
+            empty_input: EmptyInput::Block,
+        }
+    }
+
+    pub fn set_empty_input(&mut self, empty_input: EmptyInput<W>) -> &mut Self {
+        self.empty_input = empty_input;
+        self
+    }
+
+    /// Creates a port on fresh channels, returning it along with the sender
+    /// that feeds its input and the receiver that drains its output.
+    pub fn with_endpoints() -> (Self, Sender<W>, Receiver<W>) {
+        let (input_sender, input) = channel();
+        let (output, output_receiver) = channel();
+        (Self::new(input, output), input_sender, output_receiver)
+    }
+}
+
+impl<W: Clone> ReadInt<W> for ChannelPort<W> {
+    fn read_int(&mut self) -> Result<W> {
+        let closed = || ErrorKinds::IOError(IOError::ChannelClosedError).into();
+        if let EmptyInput::Block = self.empty_input {
+            return self.input.recv().map_err(|_| closed());
+        }
+        match self.input.try_recv() {
+            Ok(value) => Ok(value),
+            Err(TryRecvError::Empty) => self.empty_input.on_empty(),
+            Err(TryRecvError::Disconnected) => Err(closed()),
+        }
+    }
+}
+
+impl<W> WriteInt<W> for ChannelPort<W> {
+    fn write_int(&mut self, i: W) -> Result<()> {
+        // The reader may already have halted, as the first amplifier in a
+        // feedback loop does before the last one finishes. The executor
+        // still yields the value, so it isn't lost.
+        let _ = self.output.send(i);
+        Ok(())
+    }
+}
+
+/// Runs `vm` on its own thread. The handle resolves to every value the vm
+/// output.
+pub fn spawn<V, W>(vm: V, port: ChannelPort<W>) -> JoinHandle<Result<Vec<W>>>
+where
+    V: VMType<W> + Send + 'static,
+    W: Word,
+{
+    thread::spawn(move || Executor::run(vm, port).collect())
+}
+
+/// Like `spawn`, but wires the vm to fresh channels and returns their
+/// outside ends.
+pub fn spawn_with_endpoints<V, W>(vm: V) -> (JoinHandle<Result<Vec<W>>>, Sender<W>, Receiver<W>)
+where
+    V: VMType<W> + Send + 'static,
+    W: Word,
+{
+    let (port, input, output) = ChannelPort::with_endpoints();
+    (spawn(vm, port), input, output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::testing::vm;
+    use anyhow::Error;
+
+    fn join(handle: JoinHandle<Result<Vec<i64>>>) -> Result<Vec<i64>> {
+        handle
+            .join()
+            .map_err(|_| Error::msg("the vm thread panicked"))?
+    }
+
+    #[test]
+    fn talks_to_a_vm_on_another_thread() -> Result<()> {
+        let (handle, input, output) = spawn_with_endpoints(vm("3,9,1002,9,2,9,4,9,99,0")?);
+        input.send(21)?;
+        assert_eq!(output.recv()?, 42);
+        assert_eq!(join(handle)?, vec![42]);
+        Ok(())
+    }
+
+    #[test]
+    fn fails_when_the_input_hangs_up() -> Result<()> {
+        let (handle, input, _output) = spawn_with_endpoints(vm("3,0,99")?);
+        drop(input);
+        assert!(join(handle).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn runs_a_feedback_loop() -> Result<()> {
+        let source =
+            "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5";
+        let phases = [9, 8, 7, 6, 5];
+        let (senders, receivers): (Vec<_>, Vec<_>) = phases.iter().map(|_| channel()).unzip();
+        for (sender, &phase) in senders.iter().zip(phases.iter()) {
+            sender.send(phase)?;
+        }
+        senders[0].send(0)?;
+        let mut handles = Vec::new();
+        for (i, receiver) in receivers.into_iter().enumerate() {
+            let output = senders[(i + 1) % senders.len()].clone();
+            handles.push(spawn(vm(source)?, ChannelPort::new(receiver, output)));
+        }
+        drop(senders);
+        let last = handles.pop().expect("there should be five amplifiers");
+        assert_eq!(join(last)?.last(), Some(&139629729));
+        for handle in handles {
+            join(handle)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn supplies_a_value_when_empty() -> Result<()> {
+        let mut port = VecPort::with_empty_input(EmptyInput::Value(-1));
+        port.input(3);
+        let output: Vec<i64> =
+            Executor::run(vm("3,0,4,0,3,0,4,0,99")?, port).collect::<Result<_>>()?;
+        assert_eq!(output, vec![3, -1]);
+
+        let mut next = 0;
+        let port = VecPort::with_empty_input(EmptyInput::Callback(Box::new(move || {
+            next += 1;
+            Ok(next)
+        })));
+        let output: Vec<i64> =
+            Executor::run(vm("3,0,4,0,3,0,4,0,99")?, port).collect::<Result<_>>()?;
+        assert_eq!(output, vec![1, 2]);
+
+        let (mut port, _input, _output) = ChannelPort::with_endpoints();
+        port.set_empty_input(EmptyInput::Value(-1));
+        assert_eq!(port.read_int()?, -1);
+        Ok(())
+    }
+
+    #[test]
+    fn executor_waits_when_the_port_yields() -> Result<()> {
+        let port = VecPort::with_empty_input(EmptyInput::Yield);
+        let mut executor = Executor::run(vm("3,0,4,0,3,0,4,0,99")?, port);
+        let pending =
+            |item: Option<Result<i64>>| matches!(item, Some(Err(e)) if is_input_pending(&e));
+        assert!(pending(executor.next()));
+        assert!(executor.is_waiting_for_input());
+        assert!(
+            pending(executor.next()),
+            "it should keep waiting without input"
+        );
+        executor.port_mut().input(5);
+        assert_eq!(executor.next().transpose()?, Some(5));
+        assert!(pending(executor.next()));
+        assert!(executor.is_waiting_for_input());
+        executor.port_mut().input(6);
+        assert_eq!(executor.next().transpose()?, Some(6));
+        assert!(executor.next().is_none());
+        assert!(!executor.is_waiting_for_input());
+        Ok(())
+    }
+
+    #[test]
+    fn collecting_a_yielding_executor_fails_instead_of_truncating() -> Result<()> {
+        let mut port = VecPort::with_empty_input(EmptyInput::Yield);
+        port.input(5);
+        let executor = Executor::run(vm("3,0,4,0,3,0,4,0,99")?, port);
+        let error = executor
+            .collect::<Result<Vec<i64>>>()
+            .expect_err("the second input isn't there yet");
+        assert!(is_input_pending(&error));
+        Ok(())
+    }
+
+    fn formatted(format: Format, input: &str) -> Result<String> {
+        let mut output = Vec::new();
+        let port = Port::with_format(input.as_bytes(), &mut output, format);
+        let outputs: Vec<i64> =
+            Executor::run(vm("3,0,3,1,4,0,4,1,99")?, port).collect::<Result<_>>()?;
+        assert_eq!(outputs, vec![12, -3]);
+        Ok(String::from_utf8(output)?)
+    }
+
+    #[test]
+    fn writes_each_format() -> Result<()> {
+        assert_eq!(
+            formatted(Format::Interactive, "12\n-3\n")?,
+            "please enter an int <<< please enter an int <<< output >>> 12\noutput >>> -3\n"
+        );
+        assert_eq!(formatted(Format::Raw, "12\n-3\n")?, "12\n-3\n");
+        assert_eq!(formatted(Format::Csv, "12,-3")?, "12,-3\n");
+        assert_eq!(
+            formatted(Format::JsonLines, "12 -3")?,
+            "{\"seq\":0,\"value\":12}\n{\"seq\":1,\"value\":-3}\n"
+        );
+        assert!(formatted(Format::Raw, "12\n").is_err());
+        Ok(())
+    }
+}
@@ -2,25 +2,46 @@ use std::io::BufRead;
 
 use anyhow::Result;
 
+use super::assembler;
+use super::compiler;
 use super::errors::ErrorKinds;
-use crate::intcode::opcodes::OpCode;
+use crate::intcode::disassembler::Disassembly;
+use crate::intcode::Word;
 use std::fmt::{Display, Error, Formatter};
 
 #[derive(Debug, PartialEq, PartialOrd, Clone)]
-pub struct Program {
-    inner: Vec<i64>,
+pub struct Program<W = i64> {
+    inner: Vec<W>,
 }
 
 impl Program {
     pub fn from_reader<T: BufRead>(reader: &mut T) -> Result<Self> {
+        Self::read(reader)
+    }
+
+    pub fn from_source<T: AsRef<str>>(source: T) -> Result<Self> {
+        Self::parse(source)
+    }
+
+    pub fn from_assembly<T: AsRef<str>>(source: T) -> Result<Self> {
+        assembler::assemble(source)
+    }
+
+    pub fn compile<T: AsRef<str>>(source: T) -> Result<Self> {
+        compiler::compile(source)
+    }
+}
+
+impl<W: Word> Program<W> {
+    pub fn read<T: BufRead>(reader: &mut T) -> Result<Self> {
         let mut s = String::new();
         reader
             .read_to_string(&mut s)
             .map_err(ErrorKinds::ReadToString)?;
-        Self::from_source(&s)
+        Self::parse(&s)
     }
 
-    pub fn from_source<T: AsRef<str>>(source: T) -> Result<Self> {
+    pub fn parse<T: AsRef<str>>(source: T) -> Result<Self> {
         let mut vec = Vec::new();
         for inst in source.as_ref().split(',') {
             vec.push(
@@ -32,46 +53,23 @@ impl Program {
         Ok(Self { inner: vec })
     }
 
-    pub fn load(&self) -> Vec<i64> {
+    pub fn as_inner(&self) -> &[W] {
+        &self.inner
+    }
+
+    pub fn load(&self) -> Vec<W> {
         self.inner.clone()
     }
+}
 
-    pub fn load_to(&self, output: &mut [i64]) -> Result<()> {
-        if output.len() < self.inner.len() {
-            println!("output: {}, program: {}", output.len(), self.inner.len());
-            return Err(ErrorKinds::NotEnoughMemoryToLoadProgramError.into());
-        }
-        for (l, r) in self.inner.iter().zip(output.iter_mut()) {
-            *r = *l
-        }
-        Ok(())
+impl<W> From<Vec<W>> for Program<W> {
+    fn from(inner: Vec<W>) -> Self {
+        Self { inner }
     }
 }
 
-impl Display for Program {
+impl<W: Word> Display for Program<W> {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
-        let mut memory_location = 0;
-        let mut instruction_count = 0;
-        writeln!(f, "inst:mem \t\tcode\tparams")?;
-        writeln!(
-            f,
-            "------------------------------------------------------------------"
-        )?;
-        while memory_location < self.inner.len() {
-            write!(f, "{:04}:{:04}\t\t", instruction_count, memory_location)?;
-            match OpCode::parse(&self.inner[memory_location..]) {
-                Ok(code) => {
-                    writeln!(f, "{}", code)?;
-                    memory_location += code.len();
-                }
-                Err(_) => {
-                    let val = self.inner[memory_location];
-                    writeln!(f, "{}", val)?;
-                    memory_location += 1;
-                }
-            }
-            instruction_count += 1;
-        }
-        Ok(())
+        Disassembly::new(&self.inner).fmt(f)
     }
 }
@@ -0,0 +1,312 @@
+//! Saving and restoring the complete state of a `VM`.
+//!
+//! A snapshot is a line oriented text file:
+//!
+//! ```text
+//! intcode-snapshot 2
+//! status waiting relative -3
+//! ip 12
+//! relative_base 2000
+//! overflow wrapping
+//! decoding lenient
+//! memory 109,2000,203,-3,99
+//! high 5000 7
+//! ```
+//!
+//! * `status` is one of `running`, `exited ok`, `exited error`,
+//!   `outputting <value>` or `waiting <mode> <value>`, where `<mode>` is
+//!   `immediate`, `reference` or `relative` and names the parameter the
+//!   pending input will be written through.
+//! * `overflow` is the arithmetic overflow policy: `checked`, `wrapping` or
+//!   `saturating`. Version 1 snapshots have no `overflow` line and load as
+//!   `checked`.
+//! * `decoding` is how instructions are decoded, `strict` or `lenient`.
+//!   Version 1 snapshots load as `strict`.
+//! * `memory` holds the words from address 0 upward as comma separated
+//!   values with trailing zeros omitted.
+//! * each `high` line holds a non-zero `<address> <value>` pair from the
+//!   sparsely stored pages far above the start of memory.
+//!
+//! Attached observers are not part of a snapshot.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::intcode::decoding::Decoding;
+use crate::intcode::errors::ErrorKinds;
+use crate::intcode::memory::{Memory, SPARSE_START};
+use crate::intcode::parameters::Parameter;
+use crate::intcode::vm::InternalStatus;
+use crate::intcode::{Memory as MemoryT, OverflowPolicy, VMType, Word, VM};
+
+static HEADER: &str = "intcode-snapshot 2";
+static HEADERS: [&str; 2] = ["intcode-snapshot 1", HEADER];
+
+fn invalid<T: ToString>(line: T) -> anyhow::Error {
+    ErrorKinds::InvalidSnapshotError(line.to_string()).into()
+}
+
+fn parse<T: std::str::FromStr>(field: Option<&str>, line: &str) -> Result<T> {
+    field
+        .and_then(|field| field.parse().ok())
+        .ok_or_else(|| invalid(line))
+}
+
+fn write_status<W: Word, O: Write>(status: &InternalStatus<W>, w: &mut O) -> Result<()> {
+    match status {
+        InternalStatus::Running => writeln!(w, "status running")?,
+        InternalStatus::Exited(Ok(())) => writeln!(w, "status exited ok")?,
+        InternalStatus::Exited(Err(())) => writeln!(w, "status exited error")?,
+        InternalStatus::Outputting(value) => writeln!(w, "status outputting {}", value)?,
+        InternalStatus::WaitingOnInputTo(Parameter::Immediate(value)) => {
+            writeln!(w, "status waiting immediate {}", value)?
+        }
+        InternalStatus::WaitingOnInputTo(Parameter::Reference(address)) => {
+            writeln!(w, "status waiting reference {}", address)?
+        }
+        InternalStatus::WaitingOnInputTo(Parameter::Relative(offset)) => {
+            writeln!(w, "status waiting relative {}", offset)?
+        }
+    }
+    Ok(())
+}
+
+fn parse_status<'a, W: Word, I: Iterator<Item = &'a str>>(
+    mut fields: I,
+    line: &str,
+) -> Result<InternalStatus<W>> {
+    Ok(match (fields.next(), fields.next()) {
+        (Some("running"), None) => InternalStatus::Running,
+        (Some("exited"), Some("ok")) => InternalStatus::Exited(Ok(())),
+        (Some("exited"), Some("error")) => InternalStatus::Exited(Err(())),
+        (Some("outputting"), value) => InternalStatus::Outputting(parse(value, line)?),
+        (Some("waiting"), Some("immediate")) => {
+            InternalStatus::WaitingOnInputTo(Parameter::Immediate(parse(fields.next(), line)?))
+        }
+        (Some("waiting"), Some("reference")) => {
+            InternalStatus::WaitingOnInputTo(Parameter::Reference(parse(fields.next(), line)?))
+        }
+        (Some("waiting"), Some("relative")) => {
+            InternalStatus::WaitingOnInputTo(Parameter::Relative(parse(fields.next(), line)?))
+        }
+        _ => return Err(invalid(line)),
+    })
+}
+
+fn overflow_name(policy: OverflowPolicy) -> &'static str {
+    match policy {
+        OverflowPolicy::Checked => "checked",
+        OverflowPolicy::Wrapping => "wrapping",
+        OverflowPolicy::Saturating => "saturating",
+    }
+}
+
+fn parse_overflow(field: Option<&str>, line: &str) -> Result<OverflowPolicy> {
+    match field {
+        Some("checked") => Ok(OverflowPolicy::Checked),
+        Some("wrapping") => Ok(OverflowPolicy::Wrapping),
+        Some("saturating") => Ok(OverflowPolicy::Saturating),
+        _ => Err(invalid(line)),
+    }
+}
+
+fn decoding_name(decoding: Decoding) -> &'static str {
+    match decoding {
+        Decoding::Strict => "strict",
+        Decoding::Lenient => "lenient",
+    }
+}
+
+fn parse_decoding(field: Option<&str>, line: &str) -> Result<Decoding> {
+    match field {
+        Some("strict") => Ok(Decoding::Strict),
+        Some("lenient") => Ok(Decoding::Lenient),
+        _ => Err(invalid(line)),
+    }
+}
+
+impl<W: Word> VM<W> {
+    pub fn save_snapshot<O: Write>(&self, w: &mut O) -> Result<()> {
+        writeln!(w, "{}", HEADER)?;
+        write_status(&self.status, w)?;
+        writeln!(w, "ip {}", self.instruction_pointer)?;
+        writeln!(w, "relative_base {}", self.relative_base)?;
+        writeln!(w, "overflow {}", overflow_name(self.overflow_policy()))?;
+        writeln!(w, "decoding {}", decoding_name(self.decoding()))?;
+        let words: Vec<&W> = self
+            .memory
+            .iter()
+            .take_while(|&(address, _)| address < SPARSE_START)
+            .map(|(_, word)| word)
+            .collect();
+        let used = words
+            .iter()
+            .rposition(|word| !word.is_zero())
+            .map_or(0, |i| i + 1);
+        write!(w, "memory ")?;
+        for (i, word) in words[..used].iter().enumerate() {
+            if i > 0 {
+                write!(w, ",")?;
+            }
+            write!(w, "{}", word)?;
+        }
+        writeln!(w)?;
+        let high = self
+            .memory
+            .iter()
+            .skip_while(|&(address, _)| address < SPARSE_START)
+            .filter(|(_, value)| !value.is_zero());
+        for (address, value) in high {
+            writeln!(w, "high {} {}", address, value)?;
+        }
+        Ok(())
+    }
+
+    pub fn load_snapshot<R: BufRead>(r: &mut R) -> Result<Self> {
+        let mut lines = r.lines();
+        match lines.next() {
+            Some(Ok(ref line)) if HEADERS.contains(&line.trim()) => {}
+            Some(Ok(line)) => return Err(invalid(line)),
+            Some(Err(e)) => return Err(ErrorKinds::ReadToString(e).into()),
+            None => return Err(invalid("empty snapshot")),
+        }
+        let mut vm = Self::default();
+        let mut memory = Memory::new();
+        for line in lines {
+            let line = line.map_err(ErrorKinds::ReadToString)?;
+            let mut fields = line.split_whitespace();
+            match fields.next() {
+                Some("status") => vm.status = parse_status(fields, &line)?,
+                Some("ip") => vm.instruction_pointer = parse(fields.next(), &line)?,
+                Some("relative_base") => vm.relative_base = parse(fields.next(), &line)?,
+                Some("overflow") => {
+                    vm.set_overflow_policy(parse_overflow(fields.next(), &line)?);
+                }
+                Some("decoding") => {
+                    vm.set_decoding(parse_decoding(fields.next(), &line)?);
+                }
+                Some("memory") => {
+                    if let Some(words) = fields.next() {
+                        for (i, word) in words.split(',').enumerate() {
+                            *memory.load_mut(i).ok_or_else(|| invalid(&line))? =
+                                parse(Some(word), &line)?;
+                        }
+                    }
+                }
+                Some("high") => {
+                    let address = parse(fields.next(), &line)?;
+                    *memory.load_mut(address).ok_or_else(|| invalid(&line))? =
+                        parse(fields.next(), &line)?;
+                }
+                None => {}
+                Some(_) => return Err(invalid(&line)),
+            }
+        }
+        vm.memory = memory;
+        Ok(vm)
+    }
+
+    pub fn save_snapshot_to<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        self.save_snapshot(&mut w)?;
+        w.flush()?;
+        Ok(())
+    }
+
+    pub fn load_snapshot_from<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::load_snapshot(&mut BufReader::new(File::open(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{Program, Runable, Status, VMType};
+
+    fn round_trip(vm: &VM) -> Result<VM> {
+        let mut buffer = Vec::new();
+        vm.save_snapshot(&mut buffer)?;
+        VM::load_snapshot(&mut buffer.as_slice())
+    }
+
+    #[test]
+    fn resumes_while_waiting_on_input() -> Result<()> {
+        let program = Program::from_source("109,5000,203,7,204,7,1101,2,3,9000,99")?;
+        let mut vm = VM::new();
+        vm.load_program(&program)?;
+        assert!(matches!(vm.run(), Status::RequiresInput));
+        let mut restored = round_trip(&vm)?;
+        assert_eq!(restored.status, vm.status);
+        assert_eq!(restored.ip(), vm.ip());
+        assert_eq!(restored.relative_base(), 5000);
+        assert!(matches!(restored.run_with_input(42), Status::HasOutput(42)));
+        assert!(matches!(restored.run(), Status::Exited(Ok(()))));
+        assert_eq!(restored.load(9000), Some(&5));
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_high_memory() -> Result<()> {
+        let mut vm = VM::new();
+        vm.load_program(&Program::from_source("1101,2,3,2000000000,99")?)?;
+        assert!(matches!(vm.run(), Status::Exited(Ok(()))));
+        let mut buffer = Vec::new();
+        vm.save_snapshot(&mut buffer)?;
+        assert_eq!(
+            String::from_utf8(buffer)?,
+            "intcode-snapshot 2\nstatus exited ok\nip 4\nrelative_base 0\noverflow checked\ndecoding strict\nmemory 1101,2,3,2000000000,99\nhigh 2000000000 5\n"
+        );
+        let restored = round_trip(&vm)?;
+        assert_eq!(restored.load(2_000_000_000), Some(&5));
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_the_overflow_policy() -> Result<()> {
+        let mut vm = VM::new();
+        vm.set_overflow_policy(OverflowPolicy::Wrapping);
+        vm.load_program(&Program::from_source(
+            "1101,9223372036854775807,1,13,4,13,1101,9223372036854775807,1,13,4,13,99,0",
+        )?)?;
+        assert!(matches!(vm.run(), Status::HasOutput(i64::MIN)));
+        let mut restored = round_trip(&vm)?;
+        assert_eq!(restored.overflow_policy(), OverflowPolicy::Wrapping);
+        assert!(matches!(restored.run(), Status::HasOutput(i64::MIN)));
+        assert!(matches!(restored.run(), Status::Exited(Ok(()))));
+
+        let old = "intcode-snapshot 1\nstatus running\nip 0\nrelative_base 0\nmemory 99\n";
+        let vm = VM::<i64>::load_snapshot(&mut old.as_bytes())?;
+        assert_eq!(vm.overflow_policy(), OverflowPolicy::Checked);
+        assert_eq!(vm.decoding(), Decoding::Strict);
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_the_decoding() -> Result<()> {
+        let mut vm = VM::new();
+        vm.set_decoding(Decoding::Lenient);
+        vm.load_program(&Program::from_source("109,1,304,1,304,1,99")?)?;
+        assert!(matches!(vm.run(), Status::HasOutput(304)));
+        let mut restored = round_trip(&vm)?;
+        assert_eq!(restored.decoding(), Decoding::Lenient);
+        assert!(matches!(restored.run(), Status::HasOutput(304)));
+        assert!(matches!(restored.run(), Status::Exited(Ok(()))));
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        for snapshot in &[
+            "",
+            "intcode-snapshot 3\n",
+            "intcode-snapshot 1\nip -1\n",
+            "intcode-snapshot 2\noverflow sometimes\n",
+            "intcode-snapshot 2\ndecoding loose\n",
+        ] {
+            assert!(VM::<i64>::load_snapshot(&mut snapshot.as_bytes()).is_err());
+        }
+    }
+}
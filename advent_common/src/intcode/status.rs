@@ -1,6 +1,7 @@
 #[derive(Debug)]
-pub enum Status {
+pub enum Status<W = i64> {
     Exited(anyhow::Result<()>),
-    HasOutput(i64),
+    HasOutput(W),
     RequiresInput,
+    Yielded,
 }
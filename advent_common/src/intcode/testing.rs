@@ -0,0 +1,28 @@
+//! Helpers the intcode tests share for building and running vms.
+
+use anyhow::Result;
+
+use crate::intcode::{Executor, Program, Runner, VMType, VecPort, Word, VM};
+
+pub(crate) fn vm(source: &str) -> Result<VM> {
+    load(&Program::from_source(source)?)
+}
+
+pub(crate) fn load<W: Word>(program: &Program<W>) -> Result<VM<W>> {
+    let mut vm = VM::default();
+    vm.load_program(program)?;
+    Ok(vm)
+}
+
+pub(crate) fn port<W: Word>(inputs: &[W]) -> VecPort<W> {
+    let mut port = VecPort::default();
+    for input in inputs {
+        port.input(input.clone());
+    }
+    port
+}
+
+/// Runs `vm` to the end on `inputs` and collects its output.
+pub(crate) fn run<W: Word>(vm: VM<W>, inputs: &[W]) -> Result<Vec<W>> {
+    Executor::run(vm, port(inputs)).collect()
+}
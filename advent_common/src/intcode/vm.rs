@@ -2,121 +2,399 @@ use std::fmt::Write;
 
 use anyhow::Result;
 
-use crate::intcode::errors::ErrorKinds;
-use crate::intcode::memory::Memory;
+use crate::intcode::cache::InstructionCache;
+use crate::intcode::decoding::Decoding;
+use crate::intcode::diagnostics::Fault;
+use crate::intcode::errors::{ErrorKinds, OutOfBoundsReference};
+use crate::intcode::extensions::InstructionSet;
+use crate::intcode::history::{Change, History};
+use crate::intcode::memory::{Memory, SPARSE_START};
+use crate::intcode::observer::{Execution, SharedObserver};
 use crate::intcode::opcodes::OpCode;
 use crate::intcode::parameters::Parameter;
-use crate::intcode::{Memory as MemoryT, Program, Runable, Status, VMType};
+use crate::intcode::{Memory as MemoryT, OverflowPolicy, Program, Runable, Status, VMType, Word};
 
-#[derive(Clone, Debug)]
-enum InternalStatus {
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum InternalStatus<W = i64> {
     Running,
     Exited(Result<(), ()>),
-    Outputting(i64),
-    WaitingOnInputTo(Parameter),
+    Outputting(W),
+    WaitingOnInputTo(Parameter<W>),
 }
 
 #[derive(Clone)]
-pub struct VM {
-    status: InternalStatus,
-    memory: Memory,
-    instruction_pointer: usize,
-    relative_base: i64,
+pub struct VM<W = i64> {
+    pub(crate) status: InternalStatus<W>,
+    pub(crate) memory: Memory<W>,
+    pub(crate) instruction_pointer: usize,
+    pub(crate) relative_base: W,
+    observer: Option<SharedObserver<W>>,
+    history: Option<History<W>>,
+    /// The old values of the words written since `begin_change`.
+    journal: Option<Vec<(usize, W)>>,
+    cache: InstructionCache<W>,
+    fuel: Option<u64>,
+    overflow_policy: OverflowPolicy,
+    decoding: Decoding,
+    instructions: InstructionSet<W>,
 }
 
 impl VM {
     pub fn new() -> Self {
         Default::default()
     }
+}
 
-    fn load_inst(&self) -> Result<OpCode> {
-        OpCode::parse(&self.memory.as_inner()[self.ip()..])
+impl<W: Word> VM<W> {
+    fn load_inst(&self) -> Result<OpCode<W>> {
+        let mut words: [W; 4] = Default::default();
+        self.memory.read_into(self.ip(), &mut words);
+        self.instructions.decode(&words, self.decoding)
     }
 
-    pub fn dump(&self) -> String {
-        let msg = "it should be impossible to fail here, just writing a format into memory";
-        let mut s = String::new();
-        for (i, num) in self.memory.iter().enumerate() {
-            if i > 0 {
-                write!(&mut s, ",").expect(msg);
+    fn fetch_inst(&mut self) -> Result<OpCode<W>> {
+        if let Some(code) = self.cache.get(self.instruction_pointer) {
+            return Ok(code.clone());
+        }
+        let code = self.load_inst()?;
+        self.cache.insert(self.instruction_pointer, &code);
+        Ok(code)
+    }
+
+    pub fn set_overflow_policy(&mut self, policy: OverflowPolicy) -> &mut Self {
+        self.overflow_policy = policy;
+        self
+    }
+
+    pub fn decoding(&self) -> Decoding {
+        self.decoding
+    }
+
+    pub fn set_decoding(&mut self, decoding: Decoding) -> &mut Self {
+        self.decoding = decoding;
+        self.cache.clear();
+        self
+    }
+
+    /// Decodes and runs the extensions in `instructions` as well as the
+    /// puzzle's opcodes.
+    pub fn set_instruction_set(&mut self, instructions: InstructionSet<W>) -> &mut Self {
+        self.instructions = instructions;
+        self.cache.clear();
+        self
+    }
+
+    pub fn use_instruction_cache(&mut self, enabled: bool) -> &mut Self {
+        self.cache.set_enabled(enabled);
+        self
+    }
+
+    pub fn attach_observer(&mut self, observer: SharedObserver<W>) -> &mut Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    pub fn detach_observer(&mut self) -> Option<SharedObserver<W>> {
+        self.observer.take()
+    }
+
+    fn address_of(&self, parameter: &Parameter<W>) -> Option<usize> {
+        match parameter {
+            Parameter::Reference(r) => Some(*r),
+            Parameter::Relative(r) => self.relative_address(r.clone()).ok(),
+            _ => None,
+        }
+    }
+
+    fn exec_observed(&mut self, observer: &SharedObserver<W>) -> Result<bool> {
+        let ip = self.ip();
+        let inst = self.fetch_inst()?;
+        let reads: Vec<W> = inst
+            .read_parameters()
+            .into_iter()
+            .filter_map(|p| p.read(self).ok())
+            .collect();
+        let exited = inst.clone().exec(self)?;
+        let writes: Vec<(usize, W)> = self
+            .journal
+            .iter()
+            .flatten()
+            .filter_map(|(address, _)| self.load(*address).map(|value| (*address, value.clone())))
+            .collect();
+        if let Ok(mut observer) = observer.lock() {
+            observer.on_instruction(&Execution {
+                ip,
+                code: &inst,
+                reads: &reads,
+                writes: &writes,
+            });
+        }
+        Ok(exited)
+    }
+
+    /// Runs the next instruction, journaling its writes for the history
+    /// and the observer.
+    fn exec_recorded(&mut self) -> Result<bool> {
+        let change = self.begin_change();
+        self.status = InternalStatus::Running;
+        let result = match self.observer.clone() {
+            None => self.fetch_inst().and_then(|inst| inst.exec(self)),
+            Some(observer) => self.exec_observed(&observer),
+        };
+        self.end_change(change);
+        result
+    }
+
+    pub fn record_history(&mut self, max_steps: usize) -> &mut Self {
+        self.history = Some(History::new(max_steps));
+        self
+    }
+
+    pub fn stop_recording_history(&mut self) {
+        self.history = None;
+    }
+
+    pub fn history_len(&self) -> usize {
+        self.history.as_ref().map_or(0, History::len)
+    }
+
+    /// Starts journaling writes so they can be undone or observed.
+    fn begin_change(&mut self) -> Change<W> {
+        self.journal = Some(Vec::new());
+        Change {
+            instruction_pointer: self.instruction_pointer,
+            relative_base: self.relative_base.clone(),
+            status: self.status.clone(),
+            writes: Vec::new(),
+        }
+    }
+
+    fn end_change(&mut self, mut change: Change<W>) {
+        change.writes = self.journal.take().unwrap_or_default();
+        if let Some(history) = &mut self.history {
+            history.push(change);
+        }
+    }
+
+    pub fn step_back(&mut self) -> bool {
+        let change = match self.history.as_mut().and_then(History::pop) {
+            Some(change) => change,
+            None => return false,
+        };
+        for (address, value) in change.writes.into_iter().rev() {
+            if let Some(r) = MemoryT::load_mut(self, address) {
+                *r = value;
             }
-            write!(&mut s, "{}", num).expect(msg);
         }
-        s
+        self.instruction_pointer = change.instruction_pointer;
+        self.relative_base = change.relative_base;
+        self.status = change.status;
+        true
+    }
+
+    pub fn run_back_to(&mut self, address: usize) -> bool {
+        let steps = match self.history.as_ref().and_then(|h| h.steps_back_to(address)) {
+            Some(steps) => steps,
+            None => return false,
+        };
+        for _ in 0..steps {
+            self.step_back();
+        }
+        true
+    }
+
+    pub fn current_instruction(&self) -> Result<OpCode<W>> {
+        self.load_inst()
     }
-}
 
-impl Runable for VM {
-    fn run_with_input(&mut self, input: i64) -> Status {
-        if let InternalStatus::WaitingOnInputTo(p) = self.status {
-            if let Some(err) = p.read_mut(self).map(|r| *r = input).err() {
+    pub fn relative_base(&self) -> W {
+        self.relative_base.clone()
+    }
+
+    fn fault(&self, ip: usize, relative_base: W, error: anyhow::Error) -> anyhow::Error {
+        Fault::new(
+            self,
+            &self.instructions,
+            self.decoding,
+            ip,
+            relative_base,
+            error,
+        )
+        .into()
+    }
+
+    /// Where the instruction that's waiting on input starts; it moved the ip
+    /// past itself when it ran.
+    fn input_ip(&self) -> usize {
+        self.instruction_pointer.saturating_sub(2)
+    }
+
+    pub fn step(&mut self) -> Option<Status<W>> {
+        self.check_status().or_else(|| self.exec_inst())
+    }
+
+    pub fn provide_input(&mut self, input: W) -> Result<()> {
+        if let InternalStatus::WaitingOnInputTo(p) = self.status.clone() {
+            let change = self.history.is_some().then(|| self.begin_change());
+            let address = self.address_of(&p);
+            let written = p.read_mut(self).map(|r| *r = input.clone());
+            if let Some(change) = change {
+                self.end_change(change);
+            }
+            if let Some(err) = written.err() {
                 self.status = InternalStatus::Exited(Err(()));
-                return Status::Exited(Err(err));
+                return Err(self.fault(self.input_ip(), self.relative_base(), err));
+            }
+            if let Some(observer) = &self.observer {
+                if let (Some(address), Ok(mut observer)) = (address, observer.lock()) {
+                    observer.on_input(address, input);
+                }
             }
             self.status = InternalStatus::Running;
-            self.run()
+            Ok(())
         } else {
             self.status = InternalStatus::Exited(Err(()));
-            Status::Exited(Err(ErrorKinds::UnexpectedInputError.into()))
+            Err(self.fault(
+                self.ip(),
+                self.relative_base(),
+                ErrorKinds::UnexpectedInputError.into(),
+            ))
         }
     }
 
-    fn run(&mut self) -> Status {
+    fn check_status(&mut self) -> Option<Status<W>> {
         match self.status {
             InternalStatus::WaitingOnInputTo(_) => {
                 self.status = InternalStatus::Exited(Err(()));
-                Status::Exited(Err(ErrorKinds::ExpectedInputError.into()))
+                Some(Status::Exited(Err(self.fault(
+                    self.input_ip(),
+                    self.relative_base(),
+                    ErrorKinds::ExpectedInputError.into(),
+                ))))
             }
-            InternalStatus::Exited(e) => {
-                Status::Exited(e.map_err(|_| ErrorKinds::RanAfterErrorExitError.into()))
+            InternalStatus::Exited(e) => Some(Status::Exited(e.map_err(|_| {
+                self.fault(
+                    self.ip(),
+                    self.relative_base(),
+                    ErrorKinds::RanAfterErrorExitError.into(),
+                )
+            }))),
+            _ => None,
+        }
+    }
+
+    fn exec_inst(&mut self) -> Option<Status<W>> {
+        if let Some(fuel) = &mut self.fuel {
+            if *fuel == 0 {
+                return Some(Status::Yielded);
             }
-            _ => {
-                self.status = InternalStatus::Running;
-                loop {
-                    if let Some(e) = self.load_inst().and_then(|inst| inst.exec(self)).err() {
-                        self.status = InternalStatus::Exited(Err(()));
-                        return Status::Exited(Err(e));
-                    }
-                    match &self.status {
-                        InternalStatus::Exited(e) => {
-                            return Status::Exited(
-                                e.map_err(|_| ErrorKinds::RanAfterErrorExitError.into()),
-                            )
-                        }
-                        InternalStatus::Outputting(i) => return Status::HasOutput(*i),
-                        InternalStatus::WaitingOnInputTo(_) => return Status::RequiresInput,
-                        InternalStatus::Running => continue,
-                    }
+            *fuel -= 1;
+        }
+        let (ip, relative_base) = (self.ip(), self.relative_base());
+        let result = if self.history.is_none() && self.observer.is_none() {
+            self.status = InternalStatus::Running;
+            self.fetch_inst().and_then(|inst| inst.exec(self))
+        } else {
+            self.exec_recorded()
+        };
+        if let Some(e) = result.err() {
+            self.status = InternalStatus::Exited(Err(()));
+            return Some(Status::Exited(Err(self.fault(ip, relative_base, e))));
+        }
+        match &self.status {
+            InternalStatus::Exited(e) => Some(Status::Exited(e.map_err(|_| {
+                self.fault(ip, relative_base, ErrorKinds::RanAfterErrorExitError.into())
+            }))),
+            InternalStatus::Outputting(i) => Some(Status::HasOutput(i.clone())),
+            InternalStatus::WaitingOnInputTo(_) => Some(Status::RequiresInput),
+            InternalStatus::Running => None,
+        }
+    }
+
+    /// The dense words comma separated, then an `address: value` line per
+    /// non-zero sparse word.
+    pub fn dump(&self) -> String {
+        let msg = "it should be impossible to fail here, just writing a format into memory";
+        let mut s = String::new();
+        for (address, num) in self.memory.iter() {
+            if address >= SPARSE_START {
+                if !num.is_zero() {
+                    write!(&mut s, "\n{}: {}", address, num).expect(msg);
                 }
+            } else if address > 0 {
+                write!(&mut s, ",{}", num).expect(msg);
+            } else {
+                write!(&mut s, "{}", num).expect(msg);
             }
         }
+        s
     }
 }
 
-impl super::Memory for VM {
-    fn load(&self, idx: usize) -> Option<&i64> {
+impl<W: Word> Runable<W> for VM<W> {
+    fn run_with_input(&mut self, input: W) -> Status<W> {
+        if let Some(err) = self.provide_input(input).err() {
+            return Status::Exited(Err(err));
+        }
+        self.run()
+    }
+
+    fn run(&mut self) -> Status<W> {
+        if let Some(status) = self.check_status() {
+            return status;
+        }
+        loop {
+            if let Some(status) = self.exec_inst() {
+                return status;
+            }
+        }
+    }
+
+    fn run_for(&mut self, steps: u64) -> Status<W> {
+        let fuel = self.fuel;
+        let budget = fuel.map_or(steps, |fuel| fuel.min(steps));
+        self.fuel = Some(budget);
+        let status = self.run();
+        let used = budget - self.fuel.unwrap_or(0);
+        self.fuel = fuel.map(|fuel| fuel - used);
+        status
+    }
+}
+
+impl<W: Word> super::Memory<W> for VM<W> {
+    fn load(&self, idx: usize) -> Option<&W> {
         self.memory.load(idx)
     }
 
-    fn load_mut(&mut self, idx: usize) -> Option<&mut i64> {
+    fn load_mut(&mut self, idx: usize) -> Option<&mut W> {
+        self.cache.invalidate(idx);
+        if let Some(journal) = &mut self.journal {
+            if journal.iter().all(|(address, _)| *address != idx) {
+                journal.push((idx, self.memory.load(idx).cloned().unwrap_or_default()));
+            }
+        }
         self.memory.load_mut(idx)
     }
 }
 
-impl VMType for VM {
-    fn input_to(&mut self, location: Parameter) {
+impl<W: Word> VMType<W> for VM<W> {
+    fn input_to(&mut self, location: Parameter<W>) {
         self.status = InternalStatus::WaitingOnInputTo(location);
     }
 
-    fn output(&mut self, value: i64) {
+    fn output(&mut self, value: W) {
         self.status = InternalStatus::Outputting(value);
     }
 
-    fn load_program(&mut self, program: &Program) -> Result<()> {
+    fn load_program(&mut self, program: &Program<W>) -> Result<()> {
         self.memory.zero();
-        program.load_to(self.memory.as_inner_mut())?;
+        self.cache.clear();
+        self.memory.write_slice(0, program.as_inner());
         self.status = InternalStatus::Running;
         self.instruction_pointer = 0;
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
         Ok(())
     }
 
@@ -124,16 +402,35 @@ impl VMType for VM {
         self.instruction_pointer
     }
 
-    fn offset_relative_base(&mut self, base: i64) {
-        self.relative_base += base;
+    fn offset_relative_base(&mut self, base: W) -> Result<()> {
+        self.relative_base = self
+            .overflow_policy
+            .add(&self.relative_base, &base)
+            .ok_or(ErrorKinds::ArithmeticOverflowError)?;
+        Ok(())
+    }
+
+    fn relative_address(&self, idx: W) -> Result<usize> {
+        let address = self
+            .overflow_policy
+            .add(&self.relative_base, &idx)
+            .ok_or(ErrorKinds::ArithmeticOverflowError)?;
+        if address.is_negative() {
+            return Err(ErrorKinds::ReferenceLessThanZeroError.into());
+        }
+        Ok(address.to_usize().ok_or(ErrorKinds::MemoryError(
+            OutOfBoundsReference::RelativeParameter,
+        ))?)
     }
 
-    fn load_rel(&self, idx: i64) -> Option<&i64> {
-        self.load((self.relative_base + idx) as usize)
+    fn load_rel(&self, idx: W) -> Option<&W> {
+        let address = self.relative_address(idx).ok()?;
+        self.load(address)
     }
 
-    fn load_rel_mut(&mut self, idx: i64) -> Option<&mut i64> {
-        self.load_mut((self.relative_base + idx) as usize)
+    fn load_rel_mut(&mut self, idx: W) -> Option<&mut W> {
+        let address = self.relative_address(idx).ok()?;
+        self.load_mut(address)
     }
 
     fn advance(&mut self, amount: usize) -> &mut Self {
@@ -148,15 +445,99 @@ impl VMType for VM {
     fn exit(&mut self) {
         self.status = InternalStatus::Exited(Ok(()));
     }
+
+    fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    fn overflow_policy(&self) -> OverflowPolicy {
+        self.overflow_policy
+    }
 }
 
-impl Default for VM {
+impl<W: Word> Default for VM<W> {
     fn default() -> Self {
         Self {
             status: InternalStatus::Running,
             memory: Memory::new(),
             instruction_pointer: 0,
-            relative_base: 0,
+            relative_base: W::default(),
+            observer: None,
+            history: None,
+            journal: None,
+            cache: InstructionCache::new(),
+            fuel: None,
+            overflow_policy: OverflowPolicy::default(),
+            decoding: Decoding::default(),
+            instructions: InstructionSet::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::testing::vm;
+    use crate::intcode::{Executor, Runner, VecPort};
+
+    #[test]
+    fn yields_when_the_budget_runs_out() -> Result<()> {
+        let mut vm = vm("1105,1,0")?;
+        assert!(matches!(vm.run_for(100), Status::Yielded));
+        assert_eq!(vm.ip(), 0);
+        assert!(matches!(vm.run_for(100), Status::Yielded));
+        assert_eq!(vm.fuel(), None, "run_for shouldn't leave a budget behind");
+        Ok(())
+    }
+
+    #[test]
+    fn resumes_after_yielding() -> Result<()> {
+        let mut vm = vm("1101,1,1,7,4,7,99,0")?;
+        assert!(matches!(vm.run_for(1), Status::Yielded));
+        assert_eq!(vm.load(7), Some(&2));
+        assert!(matches!(vm.run_for(1), Status::HasOutput(2)));
+        assert!(matches!(vm.run_for(0), Status::Yielded));
+        assert!(matches!(vm.run_for(1), Status::Exited(Ok(()))));
+        Ok(())
+    }
+
+    #[test]
+    fn decodes_instruction_words_of_any_size() -> Result<()> {
+        for source in &["100000099", "9223372036854775799", "-99"] {
+            let mut strict = vm(source)?;
+            assert!(matches!(strict.run(), Status::Exited(Err(_))), "{}", source);
+            let mut lenient = vm(source)?;
+            lenient.set_decoding(Decoding::Lenient);
+            let exited_ok = matches!(lenient.run(), Status::Exited(Ok(())));
+            assert_eq!(exited_ok, !source.starts_with('-'), "{}", source);
         }
+        Ok(())
+    }
+
+    #[test]
+    fn run_for_draws_from_the_fuel_tank() -> Result<()> {
+        let mut vm = vm("1105,1,0")?;
+        vm.set_fuel(Some(10));
+        assert!(matches!(vm.run_for(4), Status::Yielded));
+        assert_eq!(vm.fuel(), Some(6));
+        assert!(matches!(vm.run_for(100), Status::Yielded));
+        assert_eq!(vm.fuel(), Some(0));
+        Ok(())
+    }
+
+    #[test]
+    fn executor_reports_an_exhausted_budget() -> Result<()> {
+        let mut executor = Executor::with_budget(vm("104,1,104,2,104,3,99")?, VecPort::new(), 2);
+        assert_eq!(executor.next().transpose()?, Some(1));
+        assert_eq!(executor.next().transpose()?, Some(2));
+        assert!(executor.next().expect("there should be a result").is_err());
+        executor.vm_mut().set_fuel(None);
+        assert_eq!(executor.next().transpose()?, Some(3));
+        assert!(executor.next().is_none());
+        Ok(())
     }
 }
@@ -0,0 +1,147 @@
+use std::convert::TryFrom;
+use std::fmt::{Debug, Display};
+use std::str::FromStr;
+
+pub trait Word:
+    Clone + Debug + Display + Default + PartialEq + PartialOrd + FromStr + Send + Sync + 'static
+{
+    fn from_i64(value: i64) -> Self;
+    fn to_i64(&self) -> Option<i64>;
+    fn to_usize(&self) -> Option<usize>;
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+    fn wrapping_add(&self, other: &Self) -> Self;
+    fn wrapping_mul(&self, other: &Self) -> Self;
+    fn saturating_add(&self, other: &Self) -> Self;
+    fn saturating_mul(&self, other: &Self) -> Self;
+
+    fn is_zero(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn is_negative(&self) -> bool {
+        *self < Self::default()
+    }
+}
+
+macro_rules! primitive_word {
+    ($t:ty) => {
+        impl Word for $t {
+            fn from_i64(value: i64) -> Self {
+                <$t>::from(value)
+            }
+
+            fn to_i64(&self) -> Option<i64> {
+                i64::try_from(*self).ok()
+            }
+
+            fn to_usize(&self) -> Option<usize> {
+                usize::try_from(*self).ok()
+            }
+
+            fn checked_add(&self, other: &Self) -> Option<Self> {
+                <$t>::checked_add(*self, *other)
+            }
+
+            fn checked_mul(&self, other: &Self) -> Option<Self> {
+                <$t>::checked_mul(*self, *other)
+            }
+
+            fn wrapping_add(&self, other: &Self) -> Self {
+                <$t>::wrapping_add(*self, *other)
+            }
+
+            fn wrapping_mul(&self, other: &Self) -> Self {
+                <$t>::wrapping_mul(*self, *other)
+            }
+
+            fn saturating_add(&self, other: &Self) -> Self {
+                <$t>::saturating_add(*self, *other)
+            }
+
+            fn saturating_mul(&self, other: &Self) -> Self {
+                <$t>::saturating_mul(*self, *other)
+            }
+        }
+    };
+}
+
+primitive_word!(i64);
+primitive_word!(i128);
+
+#[cfg(feature = "bigint")]
+mod bigint {
+    use super::Word;
+    use num_bigint::BigInt;
+    use num_traits::ToPrimitive;
+
+    impl Word for BigInt {
+        fn from_i64(value: i64) -> Self {
+            BigInt::from(value)
+        }
+
+        fn to_i64(&self) -> Option<i64> {
+            ToPrimitive::to_i64(self)
+        }
+
+        fn to_usize(&self) -> Option<usize> {
+            ToPrimitive::to_usize(self)
+        }
+
+        fn checked_add(&self, other: &Self) -> Option<Self> {
+            Some(self + other)
+        }
+
+        fn checked_mul(&self, other: &Self) -> Option<Self> {
+            Some(self * other)
+        }
+
+        fn wrapping_add(&self, other: &Self) -> Self {
+            self + other
+        }
+
+        fn wrapping_mul(&self, other: &Self) -> Self {
+            self * other
+        }
+
+        fn saturating_add(&self, other: &Self) -> Self {
+            self + other
+        }
+
+        fn saturating_mul(&self, other: &Self) -> Self {
+            self * other
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intcode::{testing, Program};
+    use anyhow::Result;
+
+    fn run<W: super::Word>(source: &str) -> Result<Vec<W>> {
+        testing::run(testing::load(&Program::parse(source)?)?, &[])
+    }
+
+    #[test]
+    fn i128_words_hold_values_beyond_64_bits() -> Result<()> {
+        let source = "1102,9223372036854775807,4,9,4,9,99,0,0,0";
+        assert!(run::<i64>(source).is_err());
+        assert_eq!(run::<i128>(source)?, vec![i128::from(i64::MAX) * 4]);
+        Ok(())
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn bigint_words_never_overflow() -> Result<()> {
+        use num_bigint::BigInt;
+
+        let output =
+            run::<BigInt>("1102,170141183460469231731687303715884105727,2,9,4,9,99,0,0,0")?;
+        assert_eq!(
+            output,
+            vec!["340282366920938463463374607431768211454".parse::<BigInt>()?]
+        );
+        Ok(())
+    }
+}
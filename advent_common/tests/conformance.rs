@@ -0,0 +1,435 @@
+//! Conformance cases for the Intcode VM.
+//!
+//! Every case is a program, the inputs it's given and what it should do
+//! with them: print some outputs, leave memory starting with some words, or
+//! fail. The published examples from days 2, 5 and 9 come first, followed
+//! by cases for each opcode, parameter mode and relative base edge case
+//! they don't reach. Every case runs with and without the instruction
+//! cache.
+
+use anyhow::Result;
+
+use advent_common::intcode::{Executor, Memory, Program, Runner, VMType, VecPort, VM};
+use Expect::{Error, Memory as Mem, Output};
+
+enum Expect {
+    /// Halts after printing exactly these values.
+    Output(&'static [i64]),
+    /// Halts with memory starting with these words.
+    Memory(&'static [i64]),
+    /// Fails after printing these values.
+    Error(&'static [i64]),
+}
+
+struct Case {
+    name: &'static str,
+    program: &'static str,
+    inputs: &'static [i64],
+    expect: Expect,
+}
+
+const fn case(
+    name: &'static str,
+    program: &'static str,
+    inputs: &'static [i64],
+    expect: Expect,
+) -> Case {
+    Case {
+        name,
+        program,
+        inputs,
+        expect,
+    }
+}
+
+const EQUALS_8_POSITION: &str = "3,9,8,9,10,9,4,9,99,-1,8";
+const LESS_THAN_8_POSITION: &str = "3,9,7,9,10,9,4,9,99,-1,8";
+const EQUALS_8_IMMEDIATE: &str = "3,3,1108,-1,8,3,4,3,99";
+const LESS_THAN_8_IMMEDIATE: &str = "3,3,1107,-1,8,3,4,3,99";
+const JUMP_POSITION: &str = "3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9";
+const JUMP_IMMEDIATE: &str = "3,3,1105,-1,9,1101,0,0,12,4,12,99,1";
+const COMPARE_TO_8: &str = "3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,1106,0,36,98,0,0,\
+                            1002,21,125,20,4,20,1105,1,46,104,999,1105,1,46,1101,1000,1,20,4,20,\
+                            1105,1,46,98,99";
+const QUINE: &str = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+
+static CASES: &[Case] = &[
+    // Day 2.
+    case(
+        "day 2, first example",
+        "1,9,10,3,2,3,11,0,99,30,40,50",
+        &[],
+        Mem(&[3500, 9, 10, 70, 2, 3, 11, 0, 99, 30, 40, 50]),
+    ),
+    case("day 2, 1 + 1", "1,0,0,0,99", &[], Mem(&[2, 0, 0, 0, 99])),
+    case("day 2, 3 * 2", "2,3,0,3,99", &[], Mem(&[2, 3, 0, 6, 99])),
+    case(
+        "day 2, 99 * 99",
+        "2,4,4,5,99,0",
+        &[],
+        Mem(&[2, 4, 4, 5, 99, 9801]),
+    ),
+    case(
+        "day 2, overwriting an exit",
+        "1,1,1,4,99,5,6,0,99",
+        &[],
+        Mem(&[30, 1, 1, 4, 2, 5, 6, 0, 99]),
+    ),
+    // Day 5.
+    case("day 5, echo", "3,0,4,0,99", &[42], Output(&[42])),
+    case(
+        "day 5, parameter modes",
+        "1002,4,3,4,33",
+        &[],
+        Mem(&[1002, 4, 3, 4, 99]),
+    ),
+    case(
+        "day 5, negative immediates",
+        "1101,100,-1,4,0",
+        &[],
+        Mem(&[1101, 100, -1, 4, 99]),
+    ),
+    case(
+        "day 5, 8 == 8 by position",
+        EQUALS_8_POSITION,
+        &[8],
+        Output(&[1]),
+    ),
+    case(
+        "day 5, 7 == 8 by position",
+        EQUALS_8_POSITION,
+        &[7],
+        Output(&[0]),
+    ),
+    case(
+        "day 5, 7 < 8 by position",
+        LESS_THAN_8_POSITION,
+        &[7],
+        Output(&[1]),
+    ),
+    case(
+        "day 5, 8 < 8 by position",
+        LESS_THAN_8_POSITION,
+        &[8],
+        Output(&[0]),
+    ),
+    case(
+        "day 5, 8 == 8 immediate",
+        EQUALS_8_IMMEDIATE,
+        &[8],
+        Output(&[1]),
+    ),
+    case(
+        "day 5, 9 == 8 immediate",
+        EQUALS_8_IMMEDIATE,
+        &[9],
+        Output(&[0]),
+    ),
+    case(
+        "day 5, -3 < 8 immediate",
+        LESS_THAN_8_IMMEDIATE,
+        &[-3],
+        Output(&[1]),
+    ),
+    case(
+        "day 5, 9 < 8 immediate",
+        LESS_THAN_8_IMMEDIATE,
+        &[9],
+        Output(&[0]),
+    ),
+    case(
+        "day 5, jump on 0 by position",
+        JUMP_POSITION,
+        &[0],
+        Output(&[0]),
+    ),
+    case(
+        "day 5, jump on 5 by position",
+        JUMP_POSITION,
+        &[5],
+        Output(&[1]),
+    ),
+    case(
+        "day 5, jump on 0 immediate",
+        JUMP_IMMEDIATE,
+        &[0],
+        Output(&[0]),
+    ),
+    case(
+        "day 5, jump on -5 immediate",
+        JUMP_IMMEDIATE,
+        &[-5],
+        Output(&[1]),
+    ),
+    case("day 5, 7 compared to 8", COMPARE_TO_8, &[7], Output(&[999])),
+    case(
+        "day 5, 8 compared to 8",
+        COMPARE_TO_8,
+        &[8],
+        Output(&[1000]),
+    ),
+    case(
+        "day 5, 9 compared to 8",
+        COMPARE_TO_8,
+        &[9],
+        Output(&[1001]),
+    ),
+    // Day 9.
+    case(
+        "day 9, quine",
+        QUINE,
+        &[],
+        Output(&[
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ]),
+    ),
+    case(
+        "day 9, sixteen digits",
+        "1102,34915192,34915192,7,4,7,99,0",
+        &[],
+        Output(&[1219070632396864]),
+    ),
+    case(
+        "day 9, large immediate",
+        "104,1125899906842624,99",
+        &[],
+        Output(&[1125899906842624]),
+    ),
+    // Each opcode in each mode it accepts.
+    case(
+        "add by position",
+        "1,5,6,7,99,20,22,0",
+        &[],
+        Mem(&[1, 5, 6, 7, 99, 20, 22, 42]),
+    ),
+    case(
+        "add immediates",
+        "1101,20,22,5,99,0",
+        &[],
+        Mem(&[1101, 20, 22, 5, 99, 42]),
+    ),
+    case(
+        "add relative",
+        "109,7,22201,0,1,2,99,20,22,0",
+        &[],
+        Mem(&[109, 7, 22201, 0, 1, 2, 99, 20, 22, 42]),
+    ),
+    case(
+        "multiply mixed modes",
+        "1002,5,3,5,99,14",
+        &[],
+        Mem(&[1002, 5, 3, 5, 99, 42]),
+    ),
+    case(
+        "multiply relative",
+        "109,7,22202,0,1,2,99,6,7,0",
+        &[],
+        Mem(&[109, 7, 22202, 0, 1, 2, 99, 6, 7, 42]),
+    ),
+    case("input by position", "3,3,99,0", &[42], Mem(&[3, 3, 99, 42])),
+    case(
+        "input relative",
+        "109,7,203,0,204,0,99,0",
+        &[-42],
+        Output(&[-42]),
+    ),
+    case("output by position", "4,3,99,42", &[], Output(&[42])),
+    case("output immediate", "104,-42,99", &[], Output(&[-42])),
+    case("output relative", "109,3,204,1,99", &[], Output(&[99])),
+    case("jump if true taken", "1105,7,5,104,1,99", &[], Output(&[])),
+    case(
+        "jump if true not taken",
+        "1105,0,5,104,1,99",
+        &[],
+        Output(&[1]),
+    ),
+    case("jump if false taken", "1106,0,5,104,1,99", &[], Output(&[])),
+    case(
+        "jump if false not taken",
+        "1106,-1,5,104,1,99",
+        &[],
+        Output(&[1]),
+    ),
+    case(
+        "jump to a relative target",
+        "109,6,2105,1,0,99,7,104,5,99",
+        &[],
+        Output(&[5]),
+    ),
+    case(
+        "jump target isn't read unless the jump is taken",
+        "2106,-5,-5,104,1,99",
+        &[],
+        Output(&[1]),
+    ),
+    case(
+        "less than is strict",
+        "1107,3,3,5,99,7",
+        &[],
+        Mem(&[1107, 3, 3, 5, 99, 0]),
+    ),
+    case(
+        "less than relative",
+        "109,7,22207,0,1,2,99,-4,3,7",
+        &[],
+        Mem(&[109, 7, 22207, 0, 1, 2, 99, -4, 3, 1]),
+    ),
+    case(
+        "equals relative",
+        "109,7,22208,0,1,2,99,3,3,7",
+        &[],
+        Mem(&[109, 7, 22208, 0, 1, 2, 99, 3, 3, 1]),
+    ),
+    case("exit ignores what follows", "99,104,1", &[], Output(&[])),
+    // The relative base.
+    case("relative base starts at 0", "204,0,99", &[], Output(&[204])),
+    case(
+        "relative base accumulates",
+        "109,10,109,-7,204,-3,99",
+        &[],
+        Output(&[109]),
+    ),
+    case(
+        "relative base from memory",
+        "9,5,204,-3,99,3",
+        &[],
+        Output(&[9]),
+    ),
+    case(
+        "relative base from a relative parameter",
+        "109,7,209,0,204,0,99,1,7",
+        &[],
+        Output(&[7]),
+    ),
+    case(
+        "relative reads past the program are zero",
+        "109,1000,204,0,99",
+        &[],
+        Output(&[0]),
+    ),
+    case(
+        "relative writes far past the program",
+        "109,1000000,21101,6,7,0,204,0,99",
+        &[],
+        Output(&[13]),
+    ),
+    case(
+        "relative base can go negative",
+        "109,-5,204,8,99",
+        &[],
+        Output(&[8]),
+    ),
+    case(
+        "negative relative address",
+        "109,-5,204,4,99",
+        &[],
+        Error(&[]),
+    ),
+    // Things that should fail.
+    case("unknown opcode", "104,1,42", &[], Error(&[1])),
+    case("opcode zero", "0", &[], Error(&[])),
+    case(
+        "mode digit above 2",
+        "104,1,30001,0,0,0,99",
+        &[],
+        Error(&[1]),
+    ),
+    case(
+        "mode digit past the parameters",
+        "1104,1,99",
+        &[],
+        Error(&[]),
+    ),
+    case("negative instruction", "-1", &[], Error(&[])),
+    case("writing to an immediate", "11101,1,1,0,99", &[], Error(&[])),
+    case(
+        "writing through an immediate input",
+        "103,0,99",
+        &[1],
+        Error(&[]),
+    ),
+    case("negative position", "4,-1,99", &[], Error(&[])),
+    case(
+        "jumping to a negative address",
+        "1105,1,-1",
+        &[],
+        Error(&[]),
+    ),
+    case("running out of input", "3,0,3,0,99", &[1], Error(&[])),
+    case(
+        "running off the end of the program",
+        "104,7",
+        &[],
+        Error(&[7]),
+    ),
+    case(
+        "overflow",
+        "1101,9223372036854775807,1,0,99",
+        &[],
+        Error(&[]),
+    ),
+];
+
+fn run(case: &Case, cached: bool) -> Result<(), String> {
+    let mut vm = VM::new();
+    vm.use_instruction_cache(cached);
+    let program = Program::from_source(case.program).map_err(|e| e.to_string())?;
+    vm.load_program(&program).map_err(|e| e.to_string())?;
+    let mut port = VecPort::new();
+    for &input in case.inputs {
+        port.input(input);
+    }
+    let mut executor = Executor::run(vm, port);
+    let mut outputs = Vec::new();
+    let mut error = None;
+    for result in &mut executor {
+        match result {
+            Ok(output) => outputs.push(output),
+            Err(e) => {
+                error = Some(e);
+                break;
+            }
+        }
+    }
+    match (&case.expect, error) {
+        (Output(expected), None) if outputs == *expected => Ok(()),
+        (Output(expected), None) => Err(format!("printed {:?}, not {:?}", outputs, expected)),
+        (Mem(expected), None) => {
+            let memory: Vec<i64> = (0..expected.len())
+                .map(|address| executor.vm().load(address).copied().unwrap_or_default())
+                .collect();
+            if memory == *expected {
+                Ok(())
+            } else {
+                Err(format!("left memory {:?}, not {:?}", memory, expected))
+            }
+        }
+        (Error(expected), Some(_)) if outputs == *expected => Ok(()),
+        (Error(expected), Some(e)) => Err(format!(
+            "printed {:?} before failing with {}, not {:?}",
+            outputs, e, expected
+        )),
+        (_, Some(e)) => Err(format!("failed with {}", e)),
+        (Error(_), None) => Err(format!("halted after printing {:?}", outputs)),
+    }
+}
+
+#[test]
+fn conforms() {
+    let failures: Vec<String> = CASES
+        .iter()
+        .flat_map(|case| vec![(case, true), (case, false)])
+        .filter_map(|(case, cached)| {
+            run(case, cached).err().map(|e| {
+                let cache = if cached { "" } else { " (uncached)" };
+                format!("{}{}: {}", case.name, cache, e)
+            })
+        })
+        .collect();
+    assert!(
+        failures.is_empty(),
+        "{} cases failed:\n{}",
+        failures.len(),
+        failures.join("\n")
+    );
+}
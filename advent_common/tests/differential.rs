@@ -0,0 +1,563 @@
+//! Differential testing of `VM` against a reference interpreter.
+//!
+//! Random programs are built from well-formed instructions whose addresses
+//! mostly land in or just past the program, so they read and rewrite their
+//! own code, jump around and move the relative base. Each one is stepped on
+//! a `VM` and on `Reference`, a deliberately naive interpreter written
+//! straight from the puzzle text, and the two are compared after every
+//! instruction: what the step did, the instruction pointer, the relative
+//! base and any word written, then the memory around the program and
+//! everything the reference wrote once they stop. A
+//! mismatch is shrunk to a small reproducer before it's reported.
+//!
+//! Instructions the puzzles leave undefined, like mode digits above 2,
+//! end the comparison, and the vm's strict decoding has to reject them.
+//! `FUZZ_CASES` and `FUZZ_SEED`
+//! change how many programs are tried and where the generator starts.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{self, Display, Formatter};
+
+use advent_common::intcode::{Memory, OverflowPolicy, Program, Status, VMType, VM};
+
+const STEPS: usize = 1000;
+/// How far past the end of the program memory is compared.
+const SLACK: usize = 64;
+const EXTREMES: [i64; 6] = [i64::MAX, i64::MIN, i64::MAX / 2 + 1, -1, 0, 1];
+
+/// What a single instruction did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Event {
+    Ran,
+    Output(i64),
+    Halted,
+    Failed,
+    /// The program asked for input after the inputs ran out.
+    Starved,
+    /// The instruction isn't one the puzzles define.
+    Undefined,
+}
+
+impl Event {
+    fn is_final(self) -> bool {
+        !matches!(self, Event::Ran | Event::Output(_))
+    }
+}
+
+struct Reference {
+    memory: HashMap<usize, i64>,
+    ip: usize,
+    relative_base: i64,
+    last_write: Option<usize>,
+}
+
+impl Reference {
+    fn new(program: &[i64]) -> Self {
+        Self {
+            memory: program.iter().copied().enumerate().collect(),
+            ip: 0,
+            relative_base: 0,
+            last_write: None,
+        }
+    }
+
+    fn get(&self, address: usize) -> i64 {
+        self.memory.get(&address).copied().unwrap_or(0)
+    }
+
+    fn address(&self, mode: i64, word: i64) -> Result<usize, Event> {
+        let address = match mode {
+            0 => word,
+            _ => self.relative_base.checked_add(word).ok_or(Event::Failed)?,
+        };
+        if address < 0 {
+            return Err(Event::Failed);
+        }
+        Ok(address as usize)
+    }
+
+    fn read(&self, (mode, word): (i64, i64)) -> Result<i64, Event> {
+        match mode {
+            1 => Ok(word),
+            _ => Ok(self.get(self.address(mode, word)?)),
+        }
+    }
+
+    fn write(&mut self, (mode, word): (i64, i64), value: i64) -> Result<(), Event> {
+        if mode == 1 {
+            return Err(Event::Failed);
+        }
+        let address = self.address(mode, word)?;
+        self.memory.insert(address, value);
+        self.last_write = Some(address);
+        Ok(())
+    }
+
+    fn step(&mut self, inputs: &mut VecDeque<i64>) -> Event {
+        self.try_step(inputs).unwrap_or_else(|event| event)
+    }
+
+    fn try_step(&mut self, inputs: &mut VecDeque<i64>) -> Result<Event, Event> {
+        let instruction = self.get(self.ip);
+        if instruction < 0 {
+            return Err(Event::Failed);
+        }
+        let opcode = instruction % 100;
+        let arity = match opcode {
+            1 | 2 | 7 | 8 => 3,
+            3 | 4 | 9 => 1,
+            5 | 6 => 2,
+            99 => 0,
+            _ => return Err(Event::Failed),
+        };
+        let mut modes = instruction / 100;
+        let mut params = Vec::new();
+        for idx in 0..arity {
+            let (mode, word) = (modes % 10, self.get(self.ip + idx + 1));
+            if mode > 2 {
+                return Err(Event::Undefined);
+            }
+            if mode == 0 && word < 0 {
+                return Err(Event::Failed);
+            }
+            params.push((mode, word));
+            modes /= 10;
+        }
+        if modes != 0 {
+            return Err(Event::Undefined);
+        }
+        let next = self.ip + arity + 1;
+        let mut event = Event::Ran;
+        match opcode {
+            1 | 2 | 7 | 8 => {
+                let (left, right) = (self.read(params[0])?, self.read(params[1])?);
+                let value = match opcode {
+                    1 => left.checked_add(right).ok_or(Event::Failed)?,
+                    2 => left.checked_mul(right).ok_or(Event::Failed)?,
+                    7 => (left < right) as i64,
+                    _ => (left == right) as i64,
+                };
+                self.write(params[2], value)?;
+            }
+            3 => {
+                let value = inputs.pop_front().ok_or(Event::Starved)?;
+                self.write(params[0], value)?;
+            }
+            4 => event = Event::Output(self.read(params[0])?),
+            5 | 6 => {
+                if (self.read(params[0])? != 0) == (opcode == 5) {
+                    let target = self.read(params[1])?;
+                    if target < 0 {
+                        return Err(Event::Failed);
+                    }
+                    self.ip = target as usize;
+                    return Ok(Event::Ran);
+                }
+            }
+            9 => {
+                let offset = self.read(params[0])?;
+                self.relative_base = self
+                    .relative_base
+                    .checked_add(offset)
+                    .ok_or(Event::Failed)?;
+            }
+            _ => return Ok(Event::Halted),
+        }
+        self.ip = next;
+        Ok(event)
+    }
+}
+
+fn step_vm(vm: &mut VM, inputs: &mut VecDeque<i64>) -> Event {
+    match vm.step() {
+        None => Event::Ran,
+        Some(Status::HasOutput(value)) => Event::Output(value),
+        Some(Status::RequiresInput) => match inputs.pop_front() {
+            None => Event::Starved,
+            Some(value) => match vm.provide_input(value) {
+                Ok(()) => Event::Ran,
+                Err(_) => Event::Failed,
+            },
+        },
+        Some(Status::Exited(Ok(()))) => Event::Halted,
+        Some(Status::Exited(Err(_))) => Event::Failed,
+        Some(Status::Yielded) => panic!("the vm has no fuel limit to run out of"),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Case {
+    program: Vec<i64>,
+    inputs: Vec<i64>,
+}
+
+impl Display for Case {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let join = |words: &[i64]| {
+            words
+                .iter()
+                .map(i64::to_string)
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        writeln!(f, "program: {}", join(&self.program))?;
+        write!(f, "inputs: {}", join(&self.inputs))
+    }
+}
+
+#[derive(Debug)]
+struct Mismatch {
+    case: Case,
+    step: usize,
+    reason: String,
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.case)?;
+        write!(f, "step {}: {}", self.step, self.reason)
+    }
+}
+
+/// How a run that matched the reference ended.
+struct Run {
+    end: Event,
+    outputs: usize,
+}
+
+/// Runs `case` on a copy of `vm` and on the reference, stopping at the
+/// first difference.
+fn compare(case: &Case, vm: &VM) -> Result<Run, Mismatch> {
+    let mut vm = vm.clone();
+    vm.load_program(&Program::from(case.program.clone()))
+        .expect("loading a program into a fresh vm");
+    let mut reference = Reference::new(&case.program);
+    let mut vm_inputs: VecDeque<_> = case.inputs.iter().copied().collect();
+    let mut reference_inputs = vm_inputs.clone();
+    let mismatch = |step, reason: String| Mismatch {
+        case: case.clone(),
+        step,
+        reason,
+    };
+    let mut run = Run {
+        end: Event::Ran,
+        outputs: 0,
+    };
+    for step in 0..STEPS {
+        let expected = reference.step(&mut reference_inputs);
+        let actual = step_vm(&mut vm, &mut vm_inputs);
+        if expected == Event::Undefined {
+            if actual != Event::Failed {
+                return Err(mismatch(
+                    step,
+                    format!("the vm gave {:?} for an undefined instruction", actual),
+                ));
+            }
+            run.end = expected;
+            break;
+        }
+        if expected != actual {
+            return Err(mismatch(
+                step,
+                format!(
+                    "the reference gave {:?} but the vm gave {:?}",
+                    expected, actual
+                ),
+            ));
+        }
+        if let Event::Output(_) = actual {
+            run.outputs += 1;
+        }
+        if actual.is_final() {
+            run.end = actual;
+            break;
+        }
+        if (vm.ip(), vm.relative_base()) != (reference.ip, reference.relative_base) {
+            return Err(mismatch(
+                step,
+                format!(
+                    "the reference is at ip {} with relative base {} but the vm is at ip {} \
+                     with relative base {}",
+                    reference.ip,
+                    reference.relative_base,
+                    vm.ip(),
+                    vm.relative_base()
+                ),
+            ));
+        }
+        if let Some(address) = reference.last_write.take() {
+            let (expected, actual) = (reference.get(address), vm.load(address).copied());
+            if Some(expected) != actual {
+                return Err(mismatch(
+                    step,
+                    format!(
+                        "the reference wrote {} to {} but the vm holds {:?}",
+                        expected, address, actual
+                    ),
+                ));
+            }
+        }
+    }
+    let addresses = (0..case.program.len() + SLACK).chain(reference.memory.keys().copied());
+    for address in addresses {
+        let (expected, actual) = (reference.get(address), vm.load(address).copied());
+        if Some(expected) != actual {
+            return Err(mismatch(
+                STEPS,
+                format!(
+                    "after the run the reference holds {} at {} but the vm holds {:?}",
+                    expected, address, actual
+                ),
+            ));
+        }
+    }
+    Ok(run)
+}
+
+/// Smaller versions of `case`: with words or inputs removed, or with one of
+/// them moved towards zero.
+fn candidates(case: &Case) -> Vec<Case> {
+    let mut candidates = Vec::new();
+    for len in (1..=4).rev() {
+        for start in 0..=case.program.len().saturating_sub(len) {
+            let mut program = case.program.clone();
+            program.drain(start..(start + len).min(program.len()));
+            candidates.push(Case {
+                program,
+                inputs: case.inputs.clone(),
+            });
+        }
+    }
+    for idx in 0..case.inputs.len() {
+        let mut inputs = case.inputs.clone();
+        inputs.remove(idx);
+        candidates.push(Case {
+            program: case.program.clone(),
+            inputs,
+        });
+    }
+    // Zero first, then ever smaller steps towards it, so a value that has to
+    // stay large is homed in on rather than counted down one at a time.
+    let simpler = |word: i64| {
+        let mut words = vec![0];
+        let mut step = word / 2;
+        while step != 0 {
+            words.push(word - step);
+            step /= 2;
+        }
+        words.push(word - word.signum());
+        words.dedup();
+        words.retain(|&simpler| simpler != word);
+        words
+    };
+    for (idx, &word) in case.program.iter().enumerate() {
+        for word in simpler(word) {
+            let mut program = case.program.clone();
+            program[idx] = word;
+            candidates.push(Case {
+                program,
+                inputs: case.inputs.clone(),
+            });
+        }
+    }
+    for (idx, &input) in case.inputs.iter().enumerate() {
+        for input in simpler(input) {
+            let mut inputs = case.inputs.clone();
+            inputs[idx] = input;
+            candidates.push(Case {
+                program: case.program.clone(),
+                inputs,
+            });
+        }
+    }
+    candidates
+}
+
+/// Keeps replacing the mismatch with a smaller one until no candidate
+/// still fails.
+fn shrink(mut mismatch: Mismatch, vm: &VM) -> Mismatch {
+    while let Some(smaller) = candidates(&mismatch.case)
+        .iter()
+        .find_map(|case| compare(case, vm).err())
+    {
+        mismatch = smaller;
+    }
+    mismatch
+}
+
+/// xorshift64*, which is plenty for picking instructions.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // Spread out neighbouring seeds, and keep the state non-zero.
+        Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn range(&mut self, low: i64, high: i64) -> i64 {
+        low + self.below((high - low) as usize) as i64
+    }
+
+    fn percent(&mut self, percent: usize) -> bool {
+        self.below(100) < percent
+    }
+
+    fn value(&mut self) -> i64 {
+        if self.percent(5) {
+            EXTREMES[self.below(EXTREMES.len())]
+        } else {
+            self.range(-10, 50)
+        }
+    }
+}
+
+const OPCODES: [(i64, usize); 10] = [
+    (1, 3),
+    (2, 3),
+    (3, 1),
+    (4, 1),
+    (5, 2),
+    (6, 2),
+    (7, 3),
+    (8, 3),
+    (9, 1),
+    (99, 0),
+];
+const PLACES: [i64; 3] = [100, 1000, 10000];
+
+fn generate(rng: &mut Rng) -> Case {
+    let instructions: Vec<_> = (0..1 + rng.below(24))
+        .map(|_| OPCODES[rng.below(OPCODES.len())])
+        .collect();
+    let mut starts = Vec::new();
+    let mut len = 0;
+    for &(_, arity) in instructions.iter() {
+        starts.push(len as i64);
+        len += arity + 1;
+    }
+    let data = rng.below(8);
+    let len = len + 1 + data;
+    let mut program = Vec::with_capacity(len);
+    for (opcode, arity) in instructions {
+        let mut instruction = opcode;
+        let mut params = Vec::new();
+        for (idx, place) in PLACES.iter().enumerate().take(arity) {
+            let writes = opcode == 3 || (idx == 2 && arity == 3);
+            let mode = match rng.below(3) {
+                1 if writes && !rng.percent(2) => 0,
+                mode => mode as i64,
+            };
+            let jump_target = (opcode == 5 || opcode == 6) && idx == 1;
+            params.push(match mode {
+                0 => rng.below(len + 8) as i64,
+                1 if jump_target && !rng.percent(5) => starts[rng.below(starts.len())],
+                1 => rng.value(),
+                _ => rng.range(-4, len as i64 + 8),
+            });
+            instruction += mode * place;
+        }
+        program.push(instruction);
+        program.extend(params);
+    }
+    program.push(99);
+    program.extend((0..data).map(|_| rng.value()));
+    let inputs = (0..rng.below(6)).map(|_| rng.value()).collect();
+    Case { program, inputs }
+}
+
+fn env_or(name: &str, default: u64) -> u64 {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+fn fuzz(vm: &VM) {
+    let (seed, cases) = (env_or("FUZZ_SEED", 0), env_or("FUZZ_CASES", 500));
+    let (mut halted, mut outputs) = (0, 0);
+    for case in seed..seed + cases {
+        let generated = generate(&mut Rng::new(case));
+        match compare(&generated, vm) {
+            Ok(run) => {
+                halted += (run.end == Event::Halted) as usize;
+                outputs += run.outputs;
+            }
+            Err(mismatch) => panic!(
+                "case {} disagrees with the reference, shrunk to\n{}",
+                case,
+                shrink(mismatch, vm)
+            ),
+        }
+    }
+    assert!(
+        cases < 100 || (halted > 0 && outputs > 0),
+        "the generator should produce programs that halt and print"
+    );
+}
+
+#[test]
+fn vm_matches_the_reference() {
+    fuzz(&VM::new());
+}
+
+#[test]
+fn uncached_vm_matches_the_reference() {
+    let mut vm = VM::new();
+    vm.use_instruction_cache(false);
+    fuzz(&vm);
+}
+
+#[test]
+fn reference_runs_the_examples() {
+    let run = |program: &[i64], inputs: &[i64]| {
+        let mut reference = Reference::new(program);
+        let mut inputs = inputs.iter().copied().collect();
+        let mut outputs = Vec::new();
+        loop {
+            match reference.step(&mut inputs) {
+                Event::Ran => {}
+                Event::Output(value) => outputs.push(value),
+                end => return (end, outputs),
+            }
+        }
+    };
+    let quine = [
+        109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+    ];
+    assert_eq!(run(&quine, &[]), (Event::Halted, quine.to_vec()));
+    let equals_8 = [3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8];
+    assert_eq!(run(&equals_8, &[8]), (Event::Halted, vec![1]));
+    assert_eq!(run(&equals_8, &[7]), (Event::Halted, vec![0]));
+    assert_eq!(run(&equals_8, &[]), (Event::Starved, vec![]));
+    assert_eq!(run(&[1101, 1, 1, 0, 42], &[]), (Event::Failed, vec![]));
+    assert_eq!(run(&[30104, 1, 99], &[]), (Event::Undefined, vec![]));
+}
+
+#[test]
+fn shrinks_a_mismatch_to_a_small_reproducer() {
+    // Wrapping arithmetic carries on where the reference reports overflow.
+    let mut vm = VM::new();
+    vm.set_overflow_policy(OverflowPolicy::Wrapping);
+    let mismatch = (0..10_000)
+        .find_map(|case| compare(&generate(&mut Rng::new(case)), &vm).err())
+        .expect("some program should overflow");
+    let shrunk = shrink(mismatch, &vm);
+    assert!(
+        shrunk.case.program.len() <= 4 && shrunk.case.inputs.len() <= 1,
+        "shrunk to\n{}",
+        shrunk
+    );
+    assert!(compare(&shrunk.case, &vm).is_err());
+    assert!(compare(&shrunk.case, &VM::new()).is_ok());
+}
@@ -91,6 +91,7 @@ impl Robot {
             Status::Exited(result) => result,
             Status::HasOutput(output) => self.run_with_output(output),
             Status::RequiresInput => self.run_with_input(),
+            Status::Yielded => self.run(),
         }
     }
 
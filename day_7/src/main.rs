@@ -1,6 +1,7 @@
 use std::io::BufReader;
 
-use advent_common::intcode::{Executor, Program, Runable, Runner, Status, VMType, VecPort, VM};
+use advent_common::intcode::network::Outcome;
+use advent_common::intcode::{Executor, Network, Program, Runner, Topology, VMType, VecPort, VM};
 use itertools::Itertools;
 
 use anyhow::{Error, Result};
@@ -40,38 +41,35 @@ fn part_1(program: &Program) -> i64 {
         .expect("there should be a max")
 }
 
+fn feedback_loop(program: &Program, phases: &[i64]) -> Result<i64> {
+    let mut vms = Vec::new();
+    for _ in phases {
+        let mut vm = VM::new();
+        vm.load_program(program)?;
+        vms.push(vm);
+    }
+    let mut network = Network::new(vms, Topology::Ring);
+    for (node, &phase) in phases.iter().enumerate() {
+        network.input(node, phase);
+    }
+    network.input(0, 0);
+    match network.run() {
+        Outcome::Halted => network
+            .last_output(phases.len() - 1)
+            .copied()
+            .ok_or_else(|| Error::msg("no output")),
+        outcome => Err(Error::msg(format!(
+            "the amplifiers stopped with {:?}",
+            outcome
+        ))),
+    }
+}
+
 fn part_2(program: &Program) -> i64 {
-    let mut chain: Vec<VM> = (0..5).map(|_| VM::new()).collect();
     (5..=9)
         .permutations(5)
-        .map(move |combo| {
-            for (vm, &phase) in chain.iter_mut().zip(combo.iter()) {
-                vm.load_program(program)
-                    .expect("there should be enough memory to load the program");
-                if let Status::RequiresInput = vm.run() {
-                    vm.run_with_input(phase);
-                }
-            }
-            let mut output = 0;
-            loop {
-                let mut exited = false;
-                for vm in chain.iter_mut() {
-                    match vm.run_with_input(output) {
-                        Status::Exited(_) => exited = true,
-                        Status::HasOutput(out) => {
-                            output = out;
-                            if let Status::Exited(_) = vm.run() {
-                                exited = true;
-                            }
-                        }
-                        Status::RequiresInput => panic!("vm requested input two times in a row"),
-                    }
-                }
-                if exited {
-                    return output;
-                }
-            }
-        })
+        .map(|combo| feedback_loop(program, &combo))
+        .map(Result::unwrap)
         .max()
         .expect("there should be an answer")
 }
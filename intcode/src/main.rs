@@ -0,0 +1,336 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::iter;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use thiserror::Error;
+
+use advent_common::input::DayInput;
+use advent_common::intcode::{
+    Execution, Executor, Format, Observer, Port, Program, Runner, VMType, VecPort, VM,
+};
+
+#[derive(Error, Debug)]
+enum ErrorKinds {
+    #[error("unable to open file, encountered error {0}")]
+    UnableToOpen(io::Error),
+    #[error("unknown command {0}, try help")]
+    UnknownCommand(String),
+    #[error("unknown option {0}, try help")]
+    UnknownOption(String),
+    #[error("unexpected argument {0}, the program is already {1}")]
+    ExtraArgument(String, String),
+    #[error("{0} expects an argument")]
+    MissingArgument(String),
+    #[error("expected a number but found {0}")]
+    InvalidNumber(String),
+    #[error("unknown format {0}, expected interactive, raw, csv or json")]
+    UnknownFormat(String),
+}
+
+static HELP: &str = "\
+usage: intcode <command> [options] [program]
+
+commands:
+  run       run the program
+  disasm    print the program's disassembly
+  trace     run the program, printing each instruction as it executes
+  dump      run the program, then print its memory, with words far past
+            the program as address: value lines
+  bench     time repeated runs of the program
+  help      print this message
+
+options:
+  -i, --input <values>      comma separated input values
+  -f, --input-file <path>   read input values from a file
+  -o, --format <format>     interactive, raw, csv or json output
+  -d, --day <n>             look for day_<n>/input.txt as well as input.txt
+  -n, --runs <n>            how many times bench runs the program (default 10)
+
+The program is read from the named file, or parsed from the argument if no
+such file exists. Without an argument it's read from stdin when stdin is
+piped, and from input.txt otherwise. Input values come from stdin unless
+--input or --input-file is given.";
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Command {
+    Run,
+    Disasm,
+    Trace,
+    Dump,
+    Bench,
+    Help,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Input {
+    Stdin,
+    Values(String),
+    File(String),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Options {
+    command: Command,
+    program: Option<String>,
+    input: Input,
+    format: Option<Format>,
+    day: usize,
+    runs: usize,
+}
+
+fn argument<I: Iterator<Item = String>>(option: &str, args: &mut I) -> Result<String> {
+    Ok(args
+        .next()
+        .ok_or_else(|| ErrorKinds::MissingArgument(option.to_owned()))?)
+}
+
+fn number<I: Iterator<Item = String>>(option: &str, args: &mut I) -> Result<usize> {
+    let arg = argument(option, args)?;
+    Ok(arg.parse().map_err(|_| ErrorKinds::InvalidNumber(arg))?)
+}
+
+impl Options {
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Self> {
+        let command = match args.next().as_deref() {
+            Some("run") => Command::Run,
+            Some("disasm") => Command::Disasm,
+            Some("trace") => Command::Trace,
+            Some("dump") => Command::Dump,
+            Some("bench") => Command::Bench,
+            Some("help") | Some("-h") | Some("--help") | None => Command::Help,
+            Some(x) => return Err(ErrorKinds::UnknownCommand(x.to_owned()).into()),
+        };
+        let mut options = Self {
+            command,
+            program: None,
+            input: Input::Stdin,
+            format: None,
+            day: 0,
+            runs: 10,
+        };
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-i" | "--input" => options.input = Input::Values(argument(&arg, &mut args)?),
+                "-f" | "--input-file" => options.input = Input::File(argument(&arg, &mut args)?),
+                "-o" | "--format" => {
+                    let format = argument(&arg, &mut args)?;
+                    options.format = Some(match format.as_str() {
+                        "interactive" => Format::Interactive,
+                        "raw" => Format::Raw,
+                        "csv" => Format::Csv,
+                        "json" => Format::JsonLines,
+                        _ => return Err(ErrorKinds::UnknownFormat(format).into()),
+                    });
+                }
+                "-d" | "--day" => options.day = number(&arg, &mut args)?,
+                "-n" | "--runs" => options.runs = number(&arg, &mut args)?,
+                x if x.starts_with('-') && x.len() > 1 => {
+                    return Err(ErrorKinds::UnknownOption(x.to_owned()).into())
+                }
+                _ => match options.program.take() {
+                    Some(program) => return Err(ErrorKinds::ExtraArgument(arg, program).into()),
+                    None => options.program = Some(arg),
+                },
+            }
+        }
+        Ok(options)
+    }
+
+    fn program(&self) -> Result<Program> {
+        DayInput::new(self.day).with_input_from(self.program.as_deref(), |mut reader| {
+            Program::from_reader(&mut reader)
+        })
+    }
+
+    fn input(&self) -> Result<Box<dyn BufRead>> {
+        Ok(match &self.input {
+            Input::Stdin => Box::new(BufReader::new(io::stdin())),
+            Input::Values(values) => Box::new(io::Cursor::new(values.clone().into_bytes())),
+            Input::File(path) => Box::new(BufReader::new(
+                File::open(path).map_err(ErrorKinds::UnableToOpen)?,
+            )),
+        })
+    }
+
+    fn port<O: Write>(&self, output: O) -> Result<Port<Box<dyn BufRead>, O>> {
+        let format = self.format.unwrap_or_else(|| {
+            if self.input == Input::Stdin && atty::is(atty::Stream::Stdin) {
+                Format::Interactive
+            } else {
+                Format::Raw
+            }
+        });
+        Ok(Port::with_format(self.input()?, output, format))
+    }
+
+    fn input_values(&self) -> Result<Vec<i64>> {
+        let mut input = self.input()?;
+        let mut values = String::new();
+        input.read_to_string(&mut values)?;
+        values
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|value| !value.is_empty())
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| ErrorKinds::InvalidNumber(value.to_owned()).into())
+            })
+            .collect()
+    }
+}
+
+struct Tracer<O: Write> {
+    output: O,
+}
+
+impl<O: Write> Observer for Tracer<O> {
+    fn on_instruction(&mut self, execution: &Execution) {
+        let _ = writeln!(
+            self.output,
+            "{:04}\t{}\treads: {:?}\twrites: {:?}",
+            execution.ip, execution.code, execution.reads, execution.writes
+        );
+    }
+
+    fn on_input(&mut self, address: usize, value: i64) {
+        let _ = writeln!(self.output, "input {} -> {:04}", value, address);
+    }
+}
+
+fn load(program: &Program) -> Result<VM> {
+    let mut vm = VM::new();
+    vm.load_program(program)?;
+    Ok(vm)
+}
+
+fn run<O: Write>(options: &Options, vm: VM, output: O) -> Result<VM> {
+    let mut executor = Executor::run(vm, options.port(output)?);
+    for result in &mut executor {
+        result?;
+    }
+    Ok(executor.vm().clone())
+}
+
+fn bench(program: &Program, inputs: &[i64], runs: usize) -> Result<Duration> {
+    let start = Instant::now();
+    for _ in 0..runs {
+        let mut port = VecPort::new();
+        for &input in inputs {
+            port.input(input);
+        }
+        for result in Executor::run(load(program)?, port) {
+            result?;
+        }
+    }
+    Ok(start.elapsed())
+}
+
+fn memory(vm: &VM) -> String {
+    let dump = vm.dump();
+    let mut lines = dump.lines();
+    let low = lines.next().unwrap_or_default().trim_end_matches(",0");
+    iter::once(low).chain(lines).collect::<Vec<_>>().join("\n")
+}
+
+fn execute(options: &Options) -> Result<()> {
+    if let Command::Help = options.command {
+        println!("{}", HELP);
+        return Ok(());
+    }
+    let program = options.program()?;
+    match options.command {
+        Command::Help => {}
+        Command::Disasm => print!("{}", program),
+        Command::Run => {
+            run(options, load(&program)?, io::stdout())?;
+        }
+        Command::Trace => {
+            let mut vm = load(&program)?;
+            vm.attach_observer(Arc::new(Mutex::new(Tracer {
+                output: io::stdout(),
+            })));
+            run(options, vm, io::stdout())?;
+        }
+        Command::Dump => {
+            let vm = run(options, load(&program)?, io::stdout())?;
+            println!("{}", memory(&vm));
+        }
+        Command::Bench => {
+            let elapsed = bench(&program, &options.input_values()?, options.runs)?;
+            println!(
+                "{} runs in {:?}, {:?} per run",
+                options.runs,
+                elapsed,
+                elapsed / options.runs.max(1) as u32
+            );
+        }
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    execute(&Options::parse(std::env::args().skip(1))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(args: &[&str]) -> Result<Options> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_options() -> Result<()> {
+        let parsed = options(&["run", "-i", "1,2", "--format", "csv", "prog.txt"])?;
+        assert_eq!(parsed.command, Command::Run);
+        assert_eq!(parsed.input, Input::Values("1,2".to_owned()));
+        assert_eq!(parsed.format, Some(Format::Csv));
+        assert_eq!(parsed.program, Some("prog.txt".to_owned()));
+        assert_eq!(options(&[])?.command, Command::Help);
+        assert_eq!(options(&["bench", "-n", "3"])?.runs, 3);
+        assert!(options(&["frobnicate"]).is_err());
+        assert!(options(&["run", "--bogus"]).is_err());
+        assert!(options(&["run", "prog.txt", "--bogus"]).is_err());
+        assert!(options(&["run", "prog.txt", "-i", "1", "--bogus"]).is_err());
+        assert!(options(&["run", "prog.txt", "other.txt"]).is_err());
+        assert!(options(&["run", "-i"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn runs_and_dumps_with_flag_input() -> Result<()> {
+        let parsed = options(&["dump", "-i", "5", "3,9,1002,9,3,9,4,9,99,0"])?;
+        let program = parsed.program()?;
+        let mut output = Vec::new();
+        let vm = run(&parsed, load(&program)?, &mut output)?;
+        assert_eq!(String::from_utf8(output)?, "15\n");
+        assert_eq!(memory(&vm), "3,9,1002,9,3,9,4,9,99,15");
+        Ok(())
+    }
+
+    #[test]
+    fn dumps_high_memory() -> Result<()> {
+        let parsed = options(&["dump", "-i", "", "1101,2,3,2000000,99"])?;
+        let vm = run(&parsed, load(&parsed.program()?)?, io::sink())?;
+        assert_eq!(memory(&vm), "1101,2,3,2000000,99\n2000000: 5");
+        Ok(())
+    }
+
+    #[test]
+    fn traces_each_instruction() -> Result<()> {
+        let program = Program::from_source("3,5,4,5,99,0")?;
+        let trace = Arc::new(Mutex::new(Tracer { output: Vec::new() }));
+        let mut vm = load(&program)?;
+        vm.attach_observer(trace.clone());
+        let parsed = options(&["trace", "-i", "7"])?;
+        run(&parsed, vm, io::sink())?;
+        let trace = String::from_utf8(trace.lock().unwrap().output.clone())?;
+        assert_eq!(trace.lines().count(), 4);
+        assert!(trace.contains("input 7 -> 0005"));
+        Ok(())
+    }
+}
@@ -0,0 +1,321 @@
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+use anyhow::Result;
+use thiserror::Error;
+
+use advent_common::intcode::{Memory, Program, Status, VMType, VM};
+
+#[derive(Error, Debug)]
+enum ErrorKinds {
+    #[error("unable to open file, encountered error {0}")]
+    UnableToOpen(io::Error),
+    #[error("no file was provided for the argument to this script")]
+    NoFileProvided,
+    #[error("unknown command {0}, try help")]
+    UnknownCommand(String),
+    #[error("expected a number but found {0}")]
+    InvalidNumber(String),
+    #[error("{0} expects more arguments")]
+    MissingArgument(String),
+}
+
+/// The most words a single examine command prints.
+const MAX_EXAMINE: usize = 1024;
+
+static HELP: &str = "\
+commands:
+  b <addr>          set a breakpoint
+  d <addr>          delete a breakpoint
+  l                 list breakpoints
+  s [n]             step n instructions (default 1)
+  c                 continue until a breakpoint, input or exit
+  p                 print the vm state
+  x <addr> [n]      examine n words of memory (default 1, at most 1024)
+  w <addr> <value>  write a word of memory
+  i <value>         supply input to a waiting vm
+  h                 print this message
+  q                 quit";
+
+#[derive(Debug, PartialEq, Eq)]
+enum Command {
+    Break(usize),
+    Delete(usize),
+    List,
+    Step(usize),
+    Continue,
+    Print,
+    Examine(usize, usize),
+    Write(usize, i64),
+    Input(i64),
+    Help,
+    Quit,
+}
+
+fn number<T: std::str::FromStr>(command: &str, arg: Option<&str>) -> Result<T> {
+    let arg = arg.ok_or_else(|| ErrorKinds::MissingArgument(command.to_owned()))?;
+    Ok(arg
+        .parse()
+        .map_err(|_| ErrorKinds::InvalidNumber(arg.to_owned()))?)
+}
+
+impl Command {
+    fn parse(line: &str) -> Result<Option<Self>> {
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(command) => command,
+            None => return Ok(None),
+        };
+        Ok(Some(match command {
+            "b" | "break" => Command::Break(number(command, words.next())?),
+            "d" | "delete" => Command::Delete(number(command, words.next())?),
+            "l" | "list" => Command::List,
+            "s" | "step" => match words.next() {
+                Some(n) => Command::Step(number(command, Some(n))?),
+                None => Command::Step(1),
+            },
+            "c" | "continue" => Command::Continue,
+            "p" | "print" => Command::Print,
+            "x" | "examine" => {
+                let address = number(command, words.next())?;
+                match words.next() {
+                    Some(n) => Command::Examine(address, number(command, Some(n))?),
+                    None => Command::Examine(address, 1),
+                }
+            }
+            "w" | "write" => Command::Write(
+                number(command, words.next())?,
+                number(command, words.next())?,
+            ),
+            "i" | "input" => Command::Input(number(command, words.next())?),
+            "h" | "help" => Command::Help,
+            "q" | "quit" => Command::Quit,
+            x => return Err(ErrorKinds::UnknownCommand(x.to_owned()).into()),
+        }))
+    }
+}
+
+enum State {
+    Paused,
+    WaitingForInput,
+    Exited,
+}
+
+struct Debugger<O: Write> {
+    vm: VM,
+    state: State,
+    breakpoints: BTreeSet<usize>,
+    output: O,
+}
+
+impl<O: Write> Debugger<O> {
+    pub fn new(program: &Program, output: O) -> Result<Self> {
+        let mut vm = VM::new();
+        vm.load_program(program)?;
+        Ok(Self {
+            vm,
+            state: State::Paused,
+            breakpoints: BTreeSet::new(),
+            output,
+        })
+    }
+
+    fn print_state(&mut self) -> Result<()> {
+        write!(
+            self.output,
+            "ip: {:04}\trb: {:04}\t",
+            self.vm.ip(),
+            self.vm.relative_base()
+        )?;
+        match self.state {
+            State::Exited => writeln!(self.output, "exited")?,
+            State::WaitingForInput => writeln!(self.output, "waiting for input")?,
+            State::Paused => match self.vm.current_instruction() {
+                Ok(code) => writeln!(self.output, "{}", code)?,
+                Err(e) => writeln!(self.output, "{}", e)?,
+            },
+        }
+        Ok(())
+    }
+
+    fn step(&mut self) -> Result<()> {
+        match self.state {
+            State::Exited => writeln!(self.output, "the vm has exited")?,
+            State::WaitingForInput => writeln!(self.output, "the vm is waiting for input")?,
+            State::Paused => match self.vm.step() {
+                None | Some(Status::Yielded) => {}
+                Some(Status::HasOutput(out)) => writeln!(self.output, "output >>> {}", out)?,
+                Some(Status::RequiresInput) => self.state = State::WaitingForInput,
+                Some(Status::Exited(result)) => {
+                    self.state = State::Exited;
+                    if let Err(e) = result {
+                        writeln!(self.output, "error: {}", e)?;
+                    }
+                }
+            },
+        }
+        Ok(())
+    }
+
+    fn resume(&mut self) -> Result<()> {
+        self.step()?;
+        while let State::Paused = self.state {
+            if self.breakpoints.contains(&self.vm.ip()) {
+                writeln!(self.output, "breakpoint at {:04}", self.vm.ip())?;
+                break;
+            }
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    pub fn execute(&mut self, command: Command) -> Result<bool> {
+        match command {
+            Command::Break(address) => {
+                self.breakpoints.insert(address);
+            }
+            Command::Delete(address) => {
+                self.breakpoints.remove(&address);
+            }
+            Command::List => {
+                for address in self.breakpoints.iter() {
+                    writeln!(self.output, "{:04}", address)?;
+                }
+            }
+            Command::Step(n) => {
+                for _ in 0..n {
+                    self.step()?;
+                    if let State::Paused = self.state {
+                        continue;
+                    }
+                    break;
+                }
+                self.print_state()?;
+            }
+            Command::Continue => {
+                self.resume()?;
+                self.print_state()?;
+            }
+            Command::Print => self.print_state()?,
+            Command::Examine(address, n) => {
+                for address in (address..=usize::MAX).take(n.min(MAX_EXAMINE)) {
+                    match self.vm.load(address) {
+                        Some(value) => writeln!(self.output, "{:04}: {}", address, value)?,
+                        None => writeln!(self.output, "{:04}: out of bounds", address)?,
+                    }
+                }
+            }
+            Command::Write(address, value) => match self.vm.load_mut(address) {
+                Some(r) => *r = value,
+                None => writeln!(self.output, "{:04}: out of bounds", address)?,
+            },
+            Command::Input(value) => {
+                if let State::WaitingForInput = self.state {
+                    match self.vm.provide_input(value) {
+                        Ok(()) => self.state = State::Paused,
+                        Err(e) => {
+                            self.state = State::Exited;
+                            writeln!(self.output, "error: {}", e)?;
+                        }
+                    }
+                } else {
+                    writeln!(self.output, "the vm isn't waiting for input")?;
+                }
+                self.print_state()?;
+            }
+            Command::Help => writeln!(self.output, "{}", HELP)?,
+            Command::Quit => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+fn main() -> Result<()> {
+    let file_name = std::env::args().nth(1).ok_or(ErrorKinds::NoFileProvided)?;
+    let f = File::open(PathBuf::from(file_name)).map_err(ErrorKinds::UnableToOpen)?;
+    let program = Program::from_reader(&mut BufReader::new(f))?;
+    let mut debugger = Debugger::new(&program, io::stdout())?;
+    debugger.print_state()?;
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("(debug) ");
+        io::stdout().flush()?;
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => return Ok(()),
+        };
+        match Command::parse(&line) {
+            Ok(Some(command)) => {
+                if !debugger.execute(command)? {
+                    return Ok(());
+                }
+            }
+            Ok(None) => {}
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(source: &str, commands: &[&str]) -> Result<String> {
+        let program = Program::from_source(source)?;
+        let mut output = Vec::new();
+        {
+            let mut debugger = Debugger::new(&program, &mut output)?;
+            for command in commands {
+                if let Some(command) = Command::parse(command)? {
+                    debugger.execute(command)?;
+                }
+            }
+        }
+        Ok(String::from_utf8(output)?)
+    }
+
+    #[test]
+    fn parses_commands() -> Result<()> {
+        assert_eq!(Command::parse("b 12")?, Some(Command::Break(12)));
+        assert_eq!(Command::parse("s")?, Some(Command::Step(1)));
+        assert_eq!(Command::parse("x 4 3")?, Some(Command::Examine(4, 3)));
+        assert_eq!(Command::parse("w 4 -3")?, Some(Command::Write(4, -3)));
+        assert_eq!(Command::parse("  ")?, None);
+        assert!(Command::parse("b").is_err());
+        assert!(Command::parse("frobnicate").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn examines_without_overflowing() -> Result<()> {
+        let out = session("99", &["x 18446744073709551615 2"])?;
+        assert_eq!(out, "18446744073709551615: 0\n");
+        let out = session("99", &["x 0 100000"])?;
+        assert_eq!(out.lines().count(), MAX_EXAMINE);
+        Ok(())
+    }
+
+    #[test]
+    fn stops_at_breakpoints_and_waits_for_input() -> Result<()> {
+        let out = session(
+            "3,9,1001,9,5,9,4,9,99,0",
+            &["b 6", "c", "i 10", "c", "x 9", "c", "c"],
+        )?;
+        let expected = "\
+ip: 0002\trb: 0000\twaiting for input
+ip: 0002\trb: 0000\tadd\t\t[ left:\t&0009, right:\ti0005, out:\t&0009 ].
+breakpoint at 0006
+ip: 0006\trb: 0000\tout\t\t[ val:\t&0009 ].
+0009: 15
+output >>> 15
+ip: 0008\trb: 0000\texited
+the vm has exited
+ip: 0008\trb: 0000\texited
+";
+        assert_eq!(out, expected);
+        Ok(())
+    }
+}